@@ -1,20 +1,112 @@
 use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use alloy_primitives::{Address, Bytes, FixedBytes, B256};
 use alloy_sol_types::{sol, SolType};
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use blobstreamx::checkpoints::{find_checkpoint, Checkpoint};
+use clap::Parser;
 use ethers::abi::AbiEncode;
 use ethers::contract::abigen;
 use ethers::providers::{Http, Provider};
 use ethers::signers::LocalWallet;
-use log::{error, info};
+use fs2::FileExt;
+use log::{error, info, warn};
 use succinct_client::request::SuccinctClient;
+use tendermint::block::Header;
 use tendermintx::input::InputDataFetcher;
 
 // Note: Update ABI when updating contract.
 abigen!(BlobstreamX, "./abi/BlobstreamX.abi.json");
 
+#[derive(Parser, Debug, Clone)]
+#[command(about = "Run the BlobstreamX operator.")]
+pub struct BlobstreamXArgs {
+    /// Check at startup that the contract was initialized from a checkpoint compiled into the
+    /// binary (e.g. mocha-4-10000, see `genesis --checkpoint`). The checkpoint is compared with the
+    /// header from the configured Tendermint RPC and with the contract's header at its height.
+    #[arg(long)]
+    pub bootstrap_from_checkpoint: Option<String>,
+    /// Start even if the Tendermint RPC or the contract disagrees with the checkpoint.
+    #[arg(long)]
+    pub allow_checkpoint_mismatch: bool,
+    /// Request a proof from the contract's latest block to this block once and exit, instead of
+    /// running the operator loop.
+    #[arg(long)]
+    pub target_block: Option<u64>,
+    /// Request --target-block even if it is less than FINALITY_DELAY_BLOCKS behind the head of
+    /// the Tendermint chain.
+    #[arg(long)]
+    pub force: bool,
+}
+
+/// The latest block the operator proves, finality_delay_blocks behind the head of the Tendermint
+/// chain, so a block that is re-proposed or only known to a node ahead of its peers is never proven.
+fn latest_stable_block(latest_block: u64, finality_delay_blocks: u64) -> u64 {
+    latest_block.saturating_sub(finality_delay_blocks)
+}
+
+/// The block the operator loop requests: the latest multiple of block_interval that is at most
+/// latest_stable_block and at most data_commitment_max blocks after current_block.
+fn block_to_request(
+    current_block: u64,
+    latest_stable_block: u64,
+    data_commitment_max: u64,
+    block_interval: u64,
+) -> u64 {
+    let max_block = std::cmp::min(latest_stable_block, data_commitment_max + current_block);
+    max_block - (max_block % block_interval)
+}
+
+/// Check an explicitly requested target block is at most the latest stable block. Targets beyond it
+/// are rejected unless force is set.
+fn check_target_block(
+    target_block: u64,
+    latest_block: u64,
+    finality_delay_blocks: u64,
+    force: bool,
+) -> Result<()> {
+    let latest_stable_block = latest_stable_block(latest_block, finality_delay_blocks);
+    if target_block > latest_stable_block && !force {
+        return Err(anyhow!(
+            "target block {} is beyond the latest stable block {} ({} blocks behind the head {}), pass --force to request it anyway",
+            target_block,
+            latest_stable_block,
+            finality_delay_blocks,
+            latest_block
+        ));
+    }
+    Ok(())
+}
+
+/// Render how many blocks the contract is behind the head of the Tendermint chain as a gauge in the
+/// Prometheus text exposition format, labeled with the finality delay. The lag is at least the
+/// finality delay, so alerts should be on the lag beyond it.
+fn blocks_behind_tip_metric(
+    latest_block: u64,
+    current_block: u64,
+    finality_delay_blocks: u64,
+) -> String {
+    format!(
+        "# HELP blobstreamx_blocks_behind_tip Blocks between the head of the Tendermint chain and the contract's latest block.\n\
+         # TYPE blobstreamx_blocks_behind_tip gauge\n\
+         blobstreamx_blocks_behind_tip{{finality_delay_blocks=\"{}\"}} {}\n",
+        finality_delay_blocks,
+        latest_block.saturating_sub(current_block)
+    )
+}
+
+/// Replace the file at path with metrics, through a rename so a collector never reads a partial file.
+fn write_metrics(path: &Path, metrics: &str) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, metrics)?;
+    fs::rename(tmp_path, path)
+}
+
 struct BlobstreamXConfig {
     address: Address,
     chain_id: u32,
@@ -26,6 +118,68 @@ type NextHeaderInputTuple = sol! { tuple(uint64, bytes32) };
 
 type HeaderRangeInputTuple = sol! { tuple(uint64, bytes32, uint64) };
 
+/// Leases on in-flight requests, persisted as files so restarted or concurrent operators sharing
+/// the same lease directory don't request a proof for the same range twice. A lease expires after
+/// `duration`, so a range is requested again if its prover crashed or never fulfilled it.
+struct RequestLeases {
+    dir: PathBuf,
+    duration: Duration,
+}
+
+impl RequestLeases {
+    fn lease_path(&self, function_id: B256, trusted_block: u64, target_block: u64) -> PathBuf {
+        self.dir.join(format!(
+            "{}-{}-{}.lease",
+            function_id, trusted_block, target_block
+        ))
+    }
+
+    /// Attempt to acquire the lease for a request. Returns false if another request for the same
+    /// function and range holds an unexpired lease.
+    fn try_acquire(&self, function_id: B256, trusted_block: u64, target_block: u64) -> bool {
+        let path = self.lease_path(function_id, trusted_block, target_block);
+
+        // Taking over an expired lease removes and recreates its file, so two operators that both
+        // saw it expire could each acquire it. Serialize acquisitions on an exclusive lock of the
+        // lease directory, which is released when lock_file is dropped (or the process exits).
+        let lock_file = match OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(self.dir.join(".lock"))
+        {
+            Ok(file) => file,
+            Err(_) => return false,
+        };
+        if lock_file.lock_exclusive().is_err() {
+            return false;
+        }
+
+        // Read the time only once the lock is held, so the expiry check can't use a stale time.
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        if let Ok(contents) = fs::read_to_string(&path) {
+            let expires_at = contents.trim().parse::<u64>().unwrap_or_default();
+            if now < expires_at {
+                return false;
+            }
+            // The lease expired, so release it before re-acquiring.
+            let _ = fs::remove_file(&path);
+        }
+
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => write!(file, "{}", now + self.duration.as_secs()).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn release(&self, function_id: B256, trusted_block: u64, target_block: u64) {
+        let _ = fs::remove_file(self.lease_path(function_id, trusted_block, target_block));
+    }
+}
+
 struct BlobstreamXOperator {
     config: BlobstreamXConfig,
     ethereum_rpc_url: String,
@@ -34,6 +188,50 @@ struct BlobstreamXOperator {
     contract: BlobstreamX<Provider<Http>>,
     client: SuccinctClient,
     data_fetcher: InputDataFetcher,
+    request_leases: Option<RequestLeases>,
+    metrics_file: Option<PathBuf>,
+}
+
+/// Check the checkpoint against header, the header at its height from the Tendermint RPC, and
+/// against contract_header_hash, the contract's header hash at its height (zero if the contract
+/// has none). The contract only trusts headers it stores, so a contract without a header at the
+/// checkpoint's height is always an error: it must first be initialized (or have its genesis
+/// state updated) from the checkpoint. Errors on a mismatch, unless allow_mismatch is set, in
+/// which case it only warns.
+fn verify_bootstrap_checkpoint(
+    checkpoint: &Checkpoint,
+    header: &Header,
+    contract_header_hash: [u8; 32],
+    allow_mismatch: bool,
+) -> Result<()> {
+    if contract_header_hash == [0u8; 32] {
+        return Err(anyhow!(
+            "the contract has no header at checkpoint {}'s height {}, initialize it with the \
+             parameters from `genesis --checkpoint {}`",
+            checkpoint.name,
+            checkpoint.height,
+            checkpoint.name
+        ));
+    }
+
+    let mut result = checkpoint.verify_header(header);
+    if result.is_ok() && contract_header_hash != checkpoint.header_hash()? {
+        result = Err(anyhow!(
+            "checkpoint {} has header hash {}, but the contract's header hash at height {} is {}",
+            checkpoint.name,
+            checkpoint.header_hash,
+            checkpoint.height,
+            B256::from(contract_header_hash)
+        ));
+    }
+
+    match result {
+        Err(e) if allow_mismatch => {
+            warn!("CHECKPOINT MISMATCH (allowed): {}", e);
+            Ok(())
+        }
+        result => result,
+    }
 }
 
 impl BlobstreamXOperator {
@@ -67,6 +265,23 @@ impl BlobstreamXOperator {
 
         let data_fetcher = InputDataFetcher::default();
 
+        // Request leases are optional, and only enabled if REQUEST_LEASE_DIR is set.
+        let request_leases = env::var("REQUEST_LEASE_DIR").ok().map(|dir| {
+            fs::create_dir_all(&dir).expect("could not create REQUEST_LEASE_DIR");
+            let lease_mins = env::var("REQUEST_LEASE_MINS")
+                .unwrap_or(String::from("60"))
+                .parse::<u64>()
+                .expect("invalid REQUEST_LEASE_MINS");
+            RequestLeases {
+                dir: PathBuf::from(dir),
+                duration: Duration::from_secs(60 * lease_mins),
+            }
+        });
+
+        // Metrics are optional, and only written (e.g. for a node_exporter textfile collector) if
+        // METRICS_FILE is set.
+        let metrics_file = env::var("METRICS_FILE").ok().map(PathBuf::from);
+
         let succinct_rpc_url = env::var("SUCCINCT_RPC_URL").expect("SUCCINCT_RPC_URL must be set");
         let succinct_api_key = env::var("SUCCINCT_API_KEY").expect("SUCCINCT_API_KEY must be set");
 
@@ -104,6 +319,56 @@ impl BlobstreamXOperator {
             gateway_address,
             client,
             data_fetcher,
+            request_leases,
+            metrics_file,
+        }
+    }
+
+    /// Verify the contract was initialized from the checkpoint, and that the Tendermint RPC agrees
+    /// with it.
+    async fn verify_checkpoint(
+        &self,
+        checkpoint: &'static Checkpoint,
+        allow_mismatch: bool,
+    ) -> Result<()> {
+        let signed_header = self
+            .data_fetcher
+            .get_signed_header_from_number(checkpoint.height)
+            .await;
+        let contract_header_hash = self
+            .contract
+            .block_height_to_header_hash(checkpoint.height)
+            .await?;
+        verify_bootstrap_checkpoint(
+            checkpoint,
+            &signed_header.header,
+            contract_header_hash,
+            allow_mismatch,
+        )?;
+
+        info!(
+            "Contract matches checkpoint {}: block {}'s header hash: {}",
+            checkpoint.name, checkpoint.height, checkpoint.header_hash
+        );
+        Ok(())
+    }
+
+    /// Attempt to acquire the lease for a request. Always succeeds if request leases are disabled.
+    fn try_acquire_request_lease(
+        &self,
+        function_id: B256,
+        trusted_block: u64,
+        target_block: u64,
+    ) -> bool {
+        match &self.request_leases {
+            Some(leases) => leases.try_acquire(function_id, trusted_block, target_block),
+            None => true,
+        }
+    }
+
+    fn release_request_lease(&self, function_id: B256, trusted_block: u64, target_block: u64) {
+        if let Some(leases) = &self.request_leases {
+            leases.release(function_id, trusted_block, target_block);
         }
     }
 
@@ -172,7 +437,72 @@ impl BlobstreamXOperator {
         Ok(request_id)
     }
 
-    async fn run(&mut self, loop_delay_mins: u64, block_interval: u64, data_commitment_max: u64) {
+    /// Request a proof from the contract's latest block to target_block, and relay it if in local
+    /// mode. target_block must be at most the latest stable block, unless force is set.
+    async fn request_target_block(
+        &self,
+        target_block: u64,
+        finality_delay_blocks: u64,
+        force: bool,
+    ) -> Result<String> {
+        let next_header_function_id = FixedBytes(self.contract.next_header_function_id().await?);
+        let header_range_function_id = FixedBytes(self.contract.header_range_function_id().await?);
+        let header_range_max = self.contract.data_commitment_max().await?;
+
+        let current_block = self.contract.latest_block().await?;
+        let latest_tendermint_block_nb = self
+            .data_fetcher
+            .get_latest_signed_header()
+            .await
+            .header
+            .height
+            .value();
+        check_target_block(
+            target_block,
+            latest_tendermint_block_nb,
+            finality_delay_blocks,
+            force,
+        )?;
+        if target_block <= current_block || target_block - current_block > header_range_max {
+            return Err(anyhow!(
+                "target block {} must be in ({}, {}]",
+                target_block,
+                current_block,
+                current_block + header_range_max
+            ));
+        }
+
+        let request_id = if target_block - current_block == 1 {
+            self.request_next_header(current_block, next_header_function_id)
+                .await?
+        } else {
+            self.request_header_range(current_block, target_block, header_range_function_id)
+                .await?
+        };
+        info!(
+            "Request to block {} submitted: {}",
+            target_block, request_id
+        );
+
+        // If in local mode, this will submit the request on-chain.
+        self.client
+            .relay_proof(
+                request_id.clone(),
+                Some(self.ethereum_rpc_url.as_ref()),
+                self.wallet.clone(),
+                self.gateway_address.as_deref(),
+            )
+            .await?;
+        Ok(request_id)
+    }
+
+    async fn run(
+        &mut self,
+        loop_delay_mins: u64,
+        block_interval: u64,
+        data_commitment_max: u64,
+        finality_delay_blocks: u64,
+    ) {
         info!("Starting BlobstreamX operator");
         let header_range_max = self.contract.data_commitment_max().await.unwrap();
 
@@ -195,15 +525,31 @@ impl BlobstreamXOperator {
                 self.data_fetcher.get_latest_signed_header().await;
             let latest_tendermint_block_nb = latest_tendermint_signed_header.header.height.value();
 
-            // block_to_request is the closest interval of block_interval less than min(latest_tendermint_block_nb, data_commitment_max + current_block)
-            let max_block = std::cmp::min(
-                latest_tendermint_block_nb,
-                data_commitment_max + current_block,
+            // Only request blocks at least finality_delay_blocks behind the head.
+            let latest_stable_block =
+                latest_stable_block(latest_tendermint_block_nb, finality_delay_blocks);
+            info!(
+                "Contract is {} blocks behind the head of the Tendermint chain ({} blocks behind the latest stable block).",
+                latest_tendermint_block_nb.saturating_sub(current_block),
+                latest_stable_block.saturating_sub(current_block)
             );
-            let block_to_request = max_block - (max_block % block_interval);
+            if let Some(metrics_file) = &self.metrics_file {
+                let metrics = blocks_behind_tip_metric(
+                    latest_tendermint_block_nb,
+                    current_block,
+                    finality_delay_blocks,
+                );
+                if let Err(e) = write_metrics(metrics_file, &metrics) {
+                    error!("Writing metrics failed: {}", e);
+                }
+            }
 
-            // Subtract 1 block to ensure the block is stable.
-            let latest_stable_block = latest_tendermint_block_nb - 1;
+            let block_to_request = block_to_request(
+                current_block,
+                latest_stable_block,
+                data_commitment_max,
+                block_interval,
+            );
 
             // If block_to_request is less than head and greater than the current block in the contract, attempt to request.
             if latest_stable_block >= block_to_request && block_to_request > current_block {
@@ -217,7 +563,20 @@ impl BlobstreamXOperator {
 
                 info!("Attempting to step to block {}", target_block);
 
-                if target_block - current_block == 1 {
+                let function_id = if target_block - current_block == 1 {
+                    next_header_function_id
+                } else {
+                    header_range_function_id
+                };
+                // The lease is only released if the request fails. On success it is held until it
+                // expires, since the contract's latest block only advances once the proof is
+                // fulfilled on-chain, and until then this loop would request the same range again.
+                if !self.try_acquire_request_lease(function_id, current_block, target_block) {
+                    info!(
+                        "Request for range [{}, {}] is already in flight. Skipping.",
+                        current_block, target_block
+                    );
+                } else if target_block - current_block == 1 {
                     // Request the next header if the target block is the next block.
                     match self
                         .request_next_header(current_block, next_header_function_id)
@@ -245,6 +604,7 @@ impl BlobstreamXOperator {
                         }
                         Err(e) => {
                             error!("Next header request failed: {}", e);
+                            self.release_request_lease(function_id, current_block, target_block);
                             continue;
                         }
                     };
@@ -276,6 +636,7 @@ impl BlobstreamXOperator {
                         }
                         Err(e) => {
                             error!("Header range request failed: {}", e);
+                            self.release_request_lease(function_id, current_block, target_block);
                             continue;
                         }
                     };
@@ -294,6 +655,7 @@ async fn main() {
     env::set_var("RUST_LOG", "info");
     dotenv::dotenv().ok();
     env_logger::init();
+    let args = BlobstreamXArgs::parse();
 
     let loop_delay_mins_env = env::var("LOOP_DELAY_MINS");
     let mut loop_delay_mins = 5;
@@ -322,8 +684,186 @@ async fn main() {
             .expect("invalid DATA_COMMITMENT_MAX");
     }
 
+    let finality_delay_blocks_env = env::var("FINALITY_DELAY_BLOCKS");
+    let mut finality_delay_blocks = 1;
+    if finality_delay_blocks_env.is_ok() {
+        finality_delay_blocks = finality_delay_blocks_env
+            .unwrap()
+            .parse::<u64>()
+            .expect("invalid FINALITY_DELAY_BLOCKS");
+    }
+
     let mut operator = BlobstreamXOperator::new().await;
+    if let Some(name) = args.bootstrap_from_checkpoint {
+        let checkpoint = find_checkpoint(&name).expect("invalid --bootstrap-from-checkpoint");
+        operator
+            .verify_checkpoint(checkpoint, args.allow_checkpoint_mismatch)
+            .await
+            .expect("contract does not match checkpoint");
+    }
+    if let Some(target_block) = args.target_block {
+        operator
+            .request_target_block(target_block, finality_delay_blocks, args.force)
+            .await
+            .expect("request failed");
+        return;
+    }
     operator
-        .run(loop_delay_mins, update_delay_blocks, data_commitment_max)
+        .run(
+            loop_delay_mins,
+            update_delay_blocks,
+            data_commitment_max,
+            finality_delay_blocks,
+        )
         .await;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_verify_bootstrap_checkpoint() {
+        let fixture =
+            std::fs::read_to_string("circuits/fixtures/mocha-4/10000/header.json").unwrap();
+        let response: serde_json::Value = serde_json::from_str(&fixture).unwrap();
+        let mut header: Header =
+            serde_json::from_value(response["result"]["header"].clone()).unwrap();
+        let checkpoint = find_checkpoint("mocha-4-10000").unwrap();
+        let header_hash = checkpoint.header_hash().unwrap();
+
+        verify_bootstrap_checkpoint(checkpoint, &header, header_hash, false).unwrap();
+
+        // The contract only trusts headers it stores, so it must have been initialized from the
+        // checkpoint, even if mismatches are allowed.
+        assert!(verify_bootstrap_checkpoint(checkpoint, &header, [0u8; 32], false).is_err());
+        assert!(verify_bootstrap_checkpoint(checkpoint, &header, [0u8; 32], true).is_err());
+
+        // A mismatch with the contract or the RPC fails, unless it is allowed.
+        assert!(verify_bootstrap_checkpoint(checkpoint, &header, [1u8; 32], false).is_err());
+        verify_bootstrap_checkpoint(checkpoint, &header, [1u8; 32], true).unwrap();
+        header.version.app += 1;
+        assert!(verify_bootstrap_checkpoint(checkpoint, &header, header_hash, false).is_err());
+        verify_bootstrap_checkpoint(checkpoint, &header, header_hash, true).unwrap();
+
+        let args = BlobstreamXArgs::parse_from([
+            "blobstreamx",
+            "--bootstrap-from-checkpoint",
+            "mocha-4-10000",
+        ]);
+        assert_eq!(
+            args.bootstrap_from_checkpoint.as_deref(),
+            Some("mocha-4-10000")
+        );
+        assert!(!args.allow_checkpoint_mismatch);
+    }
+
+    #[test]
+    fn test_finality_delay_clamps_block_to_request() {
+        // The head is at 1000, so with a finality delay of 10 the latest stable block is 990.
+        let stable_block = latest_stable_block(1000, 10);
+        assert_eq!(stable_block, 990);
+        assert_eq!(latest_stable_block(5, 10), 0);
+
+        // The request is clamped to the latest stable block, then rounded down to the interval.
+        assert_eq!(block_to_request(500, stable_block, 1000, 1), 990);
+        assert_eq!(block_to_request(500, stable_block, 1000, 100), 900);
+        // The request is also clamped to data_commitment_max blocks after the current block.
+        assert_eq!(block_to_request(500, stable_block, 100, 1), 600);
+    }
+
+    #[test]
+    fn test_check_target_block_force() {
+        check_target_block(990, 1000, 10, false).unwrap();
+        assert!(check_target_block(991, 1000, 10, false).is_err());
+        assert!(check_target_block(1000, 1000, 10, false).is_err());
+        check_target_block(1000, 1000, 10, true).unwrap();
+
+        let args =
+            BlobstreamXArgs::parse_from(["blobstreamx", "--target-block", "1000", "--force"]);
+        assert_eq!(args.target_block, Some(1000));
+        assert!(args.force);
+    }
+
+    #[test]
+    fn test_blocks_behind_tip_metric() {
+        let metrics = blocks_behind_tip_metric(1000, 950, 10);
+        assert!(metrics.contains("# TYPE blobstreamx_blocks_behind_tip gauge\n"));
+        assert!(
+            metrics.ends_with("blobstreamx_blocks_behind_tip{finality_delay_blocks=\"10\"} 50\n")
+        );
+
+        // The contract can't be ahead of the head, but a lagging RPC must not underflow the gauge.
+        assert!(blocks_behind_tip_metric(950, 1000, 10).ends_with("} 0\n"));
+
+        let dir = test_lease_dir("metrics");
+        let metrics_file = dir.join("blobstreamx.prom");
+        write_metrics(&metrics_file, &metrics).unwrap();
+        assert_eq!(fs::read_to_string(&metrics_file).unwrap(), metrics);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn test_lease_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("blobstreamx-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_request_leases() {
+        let dir = test_lease_dir("leases");
+        let leases = RequestLeases {
+            dir: dir.clone(),
+            duration: Duration::from_secs(60),
+        };
+        let function_id = B256::repeat_byte(1);
+
+        assert!(leases.try_acquire(function_id, 10, 20));
+        assert!(!leases.try_acquire(function_id, 10, 20));
+        assert!(leases.try_acquire(function_id, 10, 21));
+
+        leases.release(function_id, 10, 20);
+        assert!(leases.try_acquire(function_id, 10, 20));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_request_leases_expired_takeover_is_exclusive() {
+        let dir = test_lease_dir("takeover");
+        let function_id = B256::repeat_byte(1);
+
+        for _ in 0..100 {
+            // Two operators sharing the lease directory race to take over the same expired lease.
+            fs::write(dir.join(format!("{}-10-20.lease", function_id)), "0").unwrap();
+
+            let barrier = Arc::new(Barrier::new(2));
+            let handles = (0..2)
+                .map(|_| {
+                    let barrier = barrier.clone();
+                    let leases = RequestLeases {
+                        dir: dir.clone(),
+                        duration: Duration::from_secs(60),
+                    };
+                    thread::spawn(move || {
+                        barrier.wait();
+                        leases.try_acquire(function_id, 10, 20)
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let nb_acquired = handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .filter(|acquired| *acquired)
+                .count();
+            assert_eq!(nb_acquired, 1);
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -9,8 +9,10 @@
 
 use std::env;
 
+use alloy_primitives::B256;
+use blobstreamx::checkpoints::find_checkpoint;
 use clap::Parser;
-use log::info;
+use log::{info, warn};
 use tendermintx::input::InputDataFetcher;
 
 #[derive(Parser, Debug, Clone)]
@@ -18,6 +20,21 @@ use tendermintx::input::InputDataFetcher;
 pub struct GenesisArgs {
     #[arg(long, default_value = "1")]
     pub block: u64,
+    /// Use a checkpoint compiled into the binary (e.g. mocha-4-10000) as the genesis header instead
+    /// of --block. The checkpoint is compared with the header from the configured RPC, and its
+    /// genesis parameters are printed for Deploy.s.sol (initialize, or updateGenesisState with
+    /// UPDATE_GENESIS_STATE=true).
+    #[arg(long)]
+    pub checkpoint: Option<String>,
+}
+
+/// The genesis parameters Deploy.s.sol reads from contracts/.env.
+fn genesis_parameters(height: u64, header_hash: [u8; 32]) -> String {
+    format!(
+        "GENESIS_HEIGHT={}\nGENESIS_HEADER={}",
+        height,
+        B256::from(header_hash)
+    )
 }
 
 #[tokio::main]
@@ -28,6 +45,28 @@ pub async fn main() {
     let data_fetcher = InputDataFetcher::default();
     let args = GenesisArgs::parse();
 
+    if let Some(name) = args.checkpoint {
+        let checkpoint = find_checkpoint(&name).unwrap();
+        let signed_header = data_fetcher
+            .get_signed_header_from_number(checkpoint.height)
+            .await;
+        if let Err(e) = checkpoint.verify_header(&signed_header.header) {
+            warn!(
+                "CHECKPOINT MISMATCH: the configured RPC disagrees with checkpoint {}: {}",
+                checkpoint.name, e
+            );
+        }
+        info!(
+            "Checkpoint {}: block {}'s header hash: {}",
+            checkpoint.name, checkpoint.height, checkpoint.header_hash
+        );
+        info!(
+            "Genesis parameters for contracts/.env:\n{}",
+            genesis_parameters(checkpoint.height, checkpoint.header_hash().unwrap())
+        );
+        return;
+    }
+
     let genesis_block = args.block;
 
     let signed_header = data_fetcher
@@ -36,3 +75,18 @@ pub async fn main() {
     let header_hash = signed_header.header.hash();
     info!("Block {}'s header hash: {:?}", genesis_block, header_hash);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_genesis_parameters() {
+        let checkpoint = find_checkpoint("mocha-4-10000").unwrap();
+        assert_eq!(
+            genesis_parameters(checkpoint.height, checkpoint.header_hash().unwrap()),
+            "GENESIS_HEIGHT=10000\n\
+             GENESIS_HEADER=0xa0123d5e4b8b8888a61f931ee2252d83568b97c223e0eca9795b29b8bd8cba2d"
+        );
+    }
+}
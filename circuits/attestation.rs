@@ -0,0 +1,618 @@
+use std::fmt;
+
+use anyhow::{bail, ensure, Result};
+use ethers::abi::{Token, Tokenize};
+use ethers::types::{H256, U256};
+use sha2::{Digest, Sha256};
+
+use crate::public_values::{
+    stitch_header_hashes, DataCommitmentLeaves, HeaderHashConflict, ProvenHeaderHashes,
+    PublicValuesV1,
+};
+
+/// The Solidity signature of BlobstreamX.verifyAttestation.
+pub const VERIFY_ATTESTATION_SIGNATURE: &str =
+    "verifyAttestation(uint256,(uint256,bytes32),(bytes32[],uint256,uint256))";
+
+/// DataRootTuple from the Blobstream contracts: struct DataRootTuple { uint256 height; bytes32 dataRoot; }.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataRootTuple {
+    pub height: U256,
+    pub data_root: H256,
+}
+
+/// BinaryMerkleProof from the Blobstream contracts:
+/// struct BinaryMerkleProof { bytes32[] sideNodes; uint256 key; uint256 numLeaves; }.
+/// side_nodes are ordered from the bottom of the tree up, as BinaryMerkleTree.verify expects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinaryMerkleProof {
+    pub side_nodes: Vec<H256>,
+    pub key: U256,
+    pub num_leaves: U256,
+}
+
+/// The arguments of BlobstreamX.verifyAttestation(_proofNonce, _tuple, _proof).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttestationProofData {
+    pub proof_nonce: U256,
+    pub tuple: DataRootTuple,
+    pub proof: BinaryMerkleProof,
+}
+
+impl Tokenize for AttestationProofData {
+    fn into_tokens(self) -> Vec<Token> {
+        vec![
+            Token::Uint(self.proof_nonce),
+            Token::Tuple(vec![
+                Token::Uint(self.tuple.height),
+                Token::FixedBytes(self.tuple.data_root.as_bytes().to_vec()),
+            ]),
+            Token::Tuple(vec![
+                Token::Array(
+                    self.proof
+                        .side_nodes
+                        .iter()
+                        .map(|node| Token::FixedBytes(node.as_bytes().to_vec()))
+                        .collect(),
+                ),
+                Token::Uint(self.proof.key),
+                Token::Uint(self.proof.num_leaves),
+            ]),
+        ]
+    }
+}
+
+impl AttestationProofData {
+    /// The calldata of verifyAttestation with these arguments.
+    pub fn calldata(&self) -> Vec<u8> {
+        let mut calldata = ethers::utils::id(VERIFY_ATTESTATION_SIGNATURE).to_vec();
+        calldata.extend(ethers::abi::encode(&self.clone().into_tokens()));
+        calldata
+    }
+}
+
+fn leaf_digest(leaf: &[u8]) -> [u8; 32] {
+    Sha256::digest([&[0u8][..], leaf].concat()).into()
+}
+
+fn node_digest(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    Sha256::digest([&[1u8][..], left, right].concat()).into()
+}
+
+/// Returns the root of the leaf digests, and appends the side nodes of the leaf at key from the
+/// bottom of the tree up. Splits at the largest power of two less than the number of leaves, as
+/// celestia-core and BinaryMerkleTree do.
+fn compute_side_nodes(leaves: &[[u8; 32]], key: usize, side_nodes: &mut Vec<H256>) -> [u8; 32] {
+    if leaves.len() == 1 {
+        return leaves[0];
+    }
+    let split = leaves.len().next_power_of_two() / 2;
+    let (left, right) = leaves.split_at(split);
+    if key < split {
+        let left_root = compute_side_nodes(left, key, side_nodes);
+        let right_root = compute_side_nodes(right, usize::MAX, &mut Vec::new());
+        side_nodes.push(H256(right_root));
+        node_digest(&left_root, &right_root)
+    } else {
+        let left_root = compute_side_nodes(left, usize::MAX, &mut Vec::new());
+        let right_root = compute_side_nodes(right, key - split, side_nodes);
+        side_nodes.push(H256(left_root));
+        node_digest(&left_root, &right_root)
+    }
+}
+
+/// Why the verifyAttestation arguments for a height could not be built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttestationError {
+    /// BlobstreamX stores the first data commitment at nonce 1.
+    ZeroProofNonce,
+    /// The data commitment range has no leaves.
+    EmptyRange,
+    /// The height is before the first block of the data commitment range.
+    HeightBeforeRange { height: u64, start_block: u64 },
+    /// The leaf index of the height is not below the number of leaves the data commitment commits
+    /// to. BinaryMerkleTree.verify would reject such a proof.
+    LeafIndexOutOfRange { key: u64, committed_leaf_count: u64 },
+}
+
+impl fmt::Display for AttestationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ZeroProofNonce => write!(f, "proof nonce must be at least 1"),
+            Self::EmptyRange => write!(f, "data commitment range is empty"),
+            Self::HeightBeforeRange {
+                height,
+                start_block,
+            } => write!(
+                f,
+                "height {} is before the data commitment range starting at {}",
+                height, start_block
+            ),
+            Self::LeafIndexOutOfRange {
+                key,
+                committed_leaf_count,
+            } => write!(
+                f,
+                "leaf index {} is out of range, the data commitment commits to {} leaves",
+                key, committed_leaf_count
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AttestationError {}
+
+/// Build the verifyAttestation arguments proving the data hash of height against the data commitment
+/// stored at proof_nonce, which covers [start_block, start_block + data_hashes.len()).
+/// data_hashes are the data hashes of every block in the committed range, in order, so the
+/// committed leaf count is data_hashes.len().
+/// Returns the arguments along with the data commitment they verify against.
+pub fn build_attestation_proof(
+    proof_nonce: u64,
+    start_block: u64,
+    data_hashes: &[H256],
+    height: u64,
+) -> std::result::Result<(AttestationProofData, H256), AttestationError> {
+    // BlobstreamX stores the first data commitment at nonce 1.
+    if proof_nonce == 0 {
+        return Err(AttestationError::ZeroProofNonce);
+    }
+    if data_hashes.is_empty() {
+        return Err(AttestationError::EmptyRange);
+    }
+    if height < start_block {
+        return Err(AttestationError::HeightBeforeRange {
+            height,
+            start_block,
+        });
+    }
+    let key = height - start_block;
+    let committed_leaf_count = data_hashes.len() as u64;
+    if key >= committed_leaf_count {
+        return Err(AttestationError::LeafIndexOutOfRange {
+            key,
+            committed_leaf_count,
+        });
+    }
+
+    let leaves = data_hashes
+        .iter()
+        .enumerate()
+        .map(|(i, data_hash)| {
+            leaf_digest(&ethers::abi::encode(&[
+                Token::Uint(U256::from(start_block + i as u64)),
+                Token::FixedBytes(data_hash.as_bytes().to_vec()),
+            ]))
+        })
+        .collect::<Vec<_>>();
+
+    let key = key as usize;
+    let mut side_nodes = Vec::new();
+    let data_commitment = compute_side_nodes(&leaves, key, &mut side_nodes);
+
+    Ok((
+        AttestationProofData {
+            proof_nonce: U256::from(proof_nonce),
+            tuple: DataRootTuple {
+                height: U256::from(height),
+                data_root: data_hashes[key],
+            },
+            proof: BinaryMerkleProof {
+                side_nodes,
+                key: U256::from(key),
+                num_leaves: U256::from(data_hashes.len()),
+            },
+        },
+        H256(data_commitment),
+    ))
+}
+
+/// Build the verifyAttestation arguments for height from the public values of a
+/// DataCommitmentWithDataHashesCircuit proof, whose data commitment is stored at proof_nonce.
+/// Only the committed_leaf_count leaves are part of the data commitment.
+pub fn build_attestation_proof_from_leaves(
+    proof_nonce: u64,
+    leaves: &DataCommitmentLeaves,
+    height: u64,
+) -> std::result::Result<AttestationProofData, AttestationError> {
+    let data_hashes = leaves
+        .leaves
+        .iter()
+        .take(leaves.committed_leaf_count as usize)
+        .map(|(_, data_hash)| H256(*data_hash))
+        .collect::<Vec<_>>();
+    let (attestation, _) =
+        build_attestation_proof(proof_nonce, leaves.start_height, &data_hashes, height)?;
+    Ok(attestation)
+}
+
+/// A data commitment stored by BlobstreamX, as emitted in DataCommitmentStored(proofNonce, startBlock,
+/// endBlock, dataCommitment). It covers the blocks [start_block, end_block).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredDataCommitment {
+    pub proof_nonce: u64,
+    pub start_block: u64,
+    pub end_block: u64,
+    pub data_commitment: H256,
+    /// The header hashes of start_block and end_block, which the proof of the commitment linked
+    /// (blockHeightToHeaderHash, and the HeadUpdate emitted with the commitment).
+    pub start_header: H256,
+    pub end_header: H256,
+}
+
+/// The data commitments stored by BlobstreamX, in nonce order, used to find the proof nonce of the
+/// commitment covering a height. The index may hold only some of the stored commitments, so their
+/// ranges can leave gaps, and where ranges overlap the commitment with the latest nonce is used.
+#[derive(Debug, Clone, Default)]
+pub struct CommitmentIndex {
+    commitments: Vec<StoredDataCommitment>,
+}
+
+impl CommitmentIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a stored data commitment. Its nonce must be above the last one's, as BlobstreamX
+    /// stores commitments with increasing nonces.
+    pub fn insert(&mut self, commitment: StoredDataCommitment) -> Result<()> {
+        ensure!(
+            commitment.start_block < commitment.end_block,
+            "data commitment {} has an empty range",
+            commitment.proof_nonce
+        );
+        if let Some(last) = self.commitments.last() {
+            ensure!(
+                commitment.proof_nonce > last.proof_nonce,
+                "expected a proof nonce above {}, got {}",
+                last.proof_nonce,
+                commitment.proof_nonce
+            );
+        }
+        self.commitments.push(commitment);
+        Ok(())
+    }
+
+    /// The stored data commitment covering height, if any. If several cover it, the one with the
+    /// latest nonce.
+    pub fn find(&self, height: u64) -> Option<&StoredDataCommitment> {
+        self.commitments
+            .iter()
+            .rev()
+            .find(|commitment| commitment.start_block <= height && height < commitment.end_block)
+    }
+
+    /// The ranges [start, end) below upto_height that no stored data commitment covers, starting
+    /// from the first block of the lowest commitment. Empty if the index is empty.
+    pub fn coverage_gaps(&self, upto_height: u64) -> Vec<(u64, u64)> {
+        let mut ranges = self
+            .commitments
+            .iter()
+            .map(|commitment| (commitment.start_block, commitment.end_block))
+            .collect::<Vec<_>>();
+        ranges.sort();
+        let Some(&(mut covered_until, _)) = ranges.first() else {
+            return Vec::new();
+        };
+
+        let mut gaps = Vec::new();
+        for (start_block, end_block) in ranges {
+            if start_block >= upto_height {
+                break;
+            }
+            if start_block > covered_until {
+                gaps.push((covered_until, start_block));
+            }
+            covered_until = covered_until.max(end_block);
+        }
+        if covered_until < upto_height {
+            gaps.push((covered_until, upto_height));
+        }
+        gaps
+    }
+
+    /// The header hashes linked by the stored data commitments in [start_height, end_height], and
+    /// the gaps in their coverage. Errors if two commitments disagree on the header hash of a
+    /// height (see stitch_header_hashes).
+    pub fn iter_header_hashes(
+        &self,
+        start_height: u64,
+        end_height: u64,
+    ) -> std::result::Result<ProvenHeaderHashes, HeaderHashConflict> {
+        let links = self
+            .commitments
+            .iter()
+            .map(|commitment| PublicValuesV1 {
+                trusted_header: commitment.start_header.0,
+                trusted_height: commitment.start_block,
+                target_header: commitment.end_header.0,
+                target_height: commitment.end_block,
+                data_commitment: commitment.data_commitment.0,
+            })
+            .collect::<Vec<_>>();
+        stitch_header_hashes(&links, start_height, end_height)
+    }
+
+    /// Build the verifyAttestation arguments for height, using the proof nonce of the stored data
+    /// commitment covering it. data_hashes are the data hashes of every block in that commitment's
+    /// range, and must reproduce the stored data commitment.
+    pub fn build_attestation_proof(
+        &self,
+        height: u64,
+        data_hashes: &[H256],
+    ) -> Result<AttestationProofData> {
+        let Some(stored) = self.find(height) else {
+            bail!("no stored data commitment covers height {}", height);
+        };
+        ensure!(
+            data_hashes.len() as u64 == stored.end_block - stored.start_block,
+            "expected {} data hashes for data commitment {}, got {}",
+            stored.end_block - stored.start_block,
+            stored.proof_nonce,
+            data_hashes.len()
+        );
+        let (attestation, data_commitment) =
+            build_attestation_proof(stored.proof_nonce, stored.start_block, data_hashes, height)?;
+        ensure!(
+            data_commitment == stored.data_commitment,
+            "data hashes do not match data commitment {}",
+            stored.proof_nonce
+        );
+        Ok(attestation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::utils::hex;
+
+    use super::*;
+
+    fn data_hashes() -> Vec<H256> {
+        (0..4u8).map(|i| H256([i + 1; 32])).collect()
+    }
+
+    #[test]
+    fn test_attestation_calldata_golden() {
+        let (attestation, data_commitment) =
+            build_attestation_proof(1, 10000, &data_hashes(), 10002).unwrap();
+
+        assert_eq!(
+            hex::encode(data_commitment),
+            "682c80f9b300d2750937a013e2567762f97ad2b67b69c25b63869829cac4f30d"
+        );
+        let expected_calldata = concat!(
+            "1f3302a9",
+            "0000000000000000000000000000000000000000000000000000000000000001",
+            "0000000000000000000000000000000000000000000000000000000000002712",
+            "0303030303030303030303030303030303030303030303030303030303030303",
+            "0000000000000000000000000000000000000000000000000000000000000080",
+            "0000000000000000000000000000000000000000000000000000000000000060",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "0000000000000000000000000000000000000000000000000000000000000004",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "bc34086526f04e20ecb95fbce8743c37882e6678a6411372a74e91a84178fdf2",
+            "26eaeaa55f8969ab2917b11d721e0d402ea7407dfe3503639ff07b64ba2d1272",
+        );
+        assert_eq!(hex::encode(attestation.calldata()), expected_calldata);
+    }
+
+    #[test]
+    fn test_attestation_proof_out_of_range() {
+        assert_eq!(
+            build_attestation_proof(1, 10000, &data_hashes(), 9999).unwrap_err(),
+            AttestationError::HeightBeforeRange {
+                height: 9999,
+                start_block: 10000
+            }
+        );
+        // The last committed leaf is provable, and the index just past it is rejected.
+        assert!(build_attestation_proof(1, 10000, &data_hashes(), 10003).is_ok());
+        assert_eq!(
+            build_attestation_proof(1, 10000, &data_hashes(), 10004).unwrap_err(),
+            AttestationError::LeafIndexOutOfRange {
+                key: 4,
+                committed_leaf_count: 4
+            }
+        );
+        assert_eq!(
+            build_attestation_proof(0, 10000, &data_hashes(), 10000).unwrap_err(),
+            AttestationError::ZeroProofNonce
+        );
+        assert_eq!(
+            build_attestation_proof(1, 10000, &[], 10000).unwrap_err(),
+            AttestationError::EmptyRange
+        );
+    }
+
+    #[test]
+    fn test_attestation_proof_from_leaves() {
+        // 3 committed leaves, followed by the padding leaf at 10003.
+        let leaves = DataCommitmentLeaves {
+            start_header: [1u8; 32],
+            start_height: 10000,
+            end_header: [2u8; 32],
+            end_height: 10003,
+            data_commitment: [3u8; 32],
+            committed_leaf_count: 3,
+            leaves: data_hashes()[..3]
+                .iter()
+                .enumerate()
+                .map(|(i, data_hash)| (10000 + i as u64, data_hash.0))
+                .collect(),
+        };
+        let (expected, _) = build_attestation_proof(1, 10000, &data_hashes()[..3], 10002).unwrap();
+        assert_eq!(
+            build_attestation_proof_from_leaves(1, &leaves, 10002).unwrap(),
+            expected
+        );
+        assert_eq!(
+            build_attestation_proof_from_leaves(1, &leaves, 10003).unwrap_err(),
+            AttestationError::LeafIndexOutOfRange {
+                key: 3,
+                committed_leaf_count: 3
+            }
+        );
+    }
+
+    fn commitment_index() -> CommitmentIndex {
+        let mut index = CommitmentIndex::new();
+        index
+            .insert(StoredDataCommitment {
+                proof_nonce: 1,
+                start_block: 9990,
+                end_block: 10000,
+                data_commitment: H256([0xaa; 32]),
+                start_header: H256([0x90; 32]),
+                end_header: H256([0x10; 32]),
+            })
+            .unwrap();
+        let (_, data_commitment) =
+            build_attestation_proof(2, 10000, &data_hashes(), 10000).unwrap();
+        index
+            .insert(StoredDataCommitment {
+                proof_nonce: 2,
+                start_block: 10000,
+                end_block: 10004,
+                data_commitment,
+                start_header: H256([0x10; 32]),
+                end_header: H256([0x14; 32]),
+            })
+            .unwrap();
+        index
+    }
+
+    #[test]
+    fn test_commitment_index_find() {
+        let index = commitment_index();
+        assert_eq!(index.find(9990).unwrap().proof_nonce, 1);
+        assert_eq!(index.find(9999).unwrap().proof_nonce, 1);
+        assert_eq!(index.find(10000).unwrap().proof_nonce, 2);
+        assert_eq!(index.find(10003).unwrap().proof_nonce, 2);
+        assert!(index.find(9989).is_none());
+        assert!(index.find(10004).is_none());
+
+        // Nonces must increase.
+        let mut index = commitment_index();
+        assert!(index
+            .insert(StoredDataCommitment {
+                proof_nonce: 2,
+                start_block: 10004,
+                end_block: 10008,
+                data_commitment: H256::zero(),
+                start_header: H256([0x14; 32]),
+                end_header: H256([0x18; 32]),
+            })
+            .is_err());
+    }
+
+    /// The commitment index, followed by commitments over [10010, 10020) and [10015, 10030), which
+    /// leave a gap after 10004 and overlap each other.
+    fn sparse_commitment_index() -> CommitmentIndex {
+        let mut index = commitment_index();
+        for (proof_nonce, start_block, end_block) in [(5, 10010, 10020), (6, 10015, 10030)] {
+            index
+                .insert(StoredDataCommitment {
+                    proof_nonce,
+                    start_block,
+                    end_block,
+                    data_commitment: H256([proof_nonce as u8; 32]),
+                    start_header: H256::zero(),
+                    end_header: H256::zero(),
+                })
+                .unwrap();
+        }
+        index
+    }
+
+    #[test]
+    fn test_commitment_index_overlap_prefers_latest() {
+        let index = sparse_commitment_index();
+        assert_eq!(index.find(10014).unwrap().proof_nonce, 5);
+        assert_eq!(index.find(10015).unwrap().proof_nonce, 6);
+        assert_eq!(index.find(10019).unwrap().proof_nonce, 6);
+        assert_eq!(index.find(10029).unwrap().proof_nonce, 6);
+        assert!(index.find(10007).is_none());
+        assert!(index.find(10030).is_none());
+    }
+
+    #[test]
+    fn test_commitment_index_coverage_gaps() {
+        assert!(CommitmentIndex::new().coverage_gaps(10000).is_empty());
+
+        let index = commitment_index();
+        assert!(index.coverage_gaps(10004).is_empty());
+        assert_eq!(index.coverage_gaps(10010), vec![(10004, 10010)]);
+
+        let index = sparse_commitment_index();
+        assert!(index.coverage_gaps(10002).is_empty());
+        assert_eq!(index.coverage_gaps(10012), vec![(10004, 10010)]);
+        assert_eq!(index.coverage_gaps(10030), vec![(10004, 10010)]);
+        assert_eq!(
+            index.coverage_gaps(10040),
+            vec![(10004, 10010), (10030, 10040)]
+        );
+    }
+
+    #[test]
+    fn test_commitment_index_attestation_proof() {
+        let index = commitment_index();
+
+        // The nonce is looked up from the index, so the calldata matches the golden fixture with nonce 2.
+        let attestation = index
+            .build_attestation_proof(10002, &data_hashes())
+            .unwrap();
+        let (expected, _) = build_attestation_proof(2, 10000, &data_hashes(), 10002).unwrap();
+        assert_eq!(attestation, expected);
+        assert_eq!(attestation.proof_nonce, U256::from(2));
+
+        // Data hashes that don't reproduce the stored commitment, and uncovered heights, are rejected.
+        let mut wrong_data_hashes = data_hashes();
+        wrong_data_hashes[0] = H256::zero();
+        assert!(index
+            .build_attestation_proof(10002, &wrong_data_hashes)
+            .is_err());
+        assert!(index
+            .build_attestation_proof(10002, &data_hashes()[..3])
+            .is_err());
+        assert!(index
+            .build_attestation_proof(10004, &data_hashes())
+            .is_err());
+    }
+
+    #[test]
+    fn test_commitment_index_iter_header_hashes() {
+        let mut index = commitment_index();
+        let proven = index.iter_header_hashes(9990, 10004).unwrap();
+        assert_eq!(
+            proven.iter().collect::<Vec<_>>(),
+            vec![(9990, [0x90; 32]), (10000, [0x10; 32]), (10004, [0x14; 32])]
+        );
+        assert!(proven.gaps.is_empty());
+        assert_eq!(
+            index.iter_header_hashes(9990, 10010).unwrap().gaps,
+            vec![(10004, 10010)]
+        );
+
+        // The next commitment's proof started from a different header at 10004 than the last one
+        // ended at.
+        index
+            .insert(StoredDataCommitment {
+                proof_nonce: 3,
+                start_block: 10004,
+                end_block: 10008,
+                data_commitment: H256::zero(),
+                start_header: H256([0x15; 32]),
+                end_header: H256([0x18; 32]),
+            })
+            .unwrap();
+        assert_eq!(
+            index.iter_header_hashes(9990, 10008).unwrap_err(),
+            HeaderHashConflict {
+                height: 10004,
+                first_header: [0x14; 32],
+                second_header: [0x15; 32],
+            }
+        );
+    }
+}
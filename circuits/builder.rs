@@ -1,11 +1,14 @@
+use ethers::types::H256;
 use plonky2x::backend::circuit::Circuit;
 use plonky2x::frontend::merkle::tendermint::TendermintMerkleTree;
+use plonky2x::frontend::merkle::tree::MerkleInclusionProofVariable;
 use plonky2x::frontend::vars::EvmVariable;
 use plonky2x::prelude::plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
 use plonky2x::prelude::*;
 
 use crate::consts::*;
-use crate::data_commitment::DataCommitmentOffchainInputs;
+use crate::data_commitment::{DataCommitmentOffchainInputs, DataHashProofOffchainInputs};
+use crate::utils::UtilsBuilder;
 use crate::vars::{DataCommitmentProofVariable, MapReduceSubchainVariable};
 
 /// Shared context across all data commitment mapreduce jobs.
@@ -28,8 +31,9 @@ pub trait DataCommitmentBuilder<L: PlonkParameters<D>, const D: usize> {
 
     /// Compute the data commitment from start_block to end_block. Each leaf in the merkle tree is abi.encode(data_hash, height).
     /// Note: Data commitment is exclusive of end_block.
-    /// Note: end_block should be >= start_block.
-    /// MAX_LEAVES is the maximum range of blocks that can be included in the data commitment.
+    /// MAX_LEAVES is the maximum range of blocks that can be included in the data commitment. start_block <= end_block <=
+    /// start_block + MAX_LEAVES is asserted, so a range longer than MAX_LEAVES fails to prove instead of producing the root of
+    /// only its first MAX_LEAVES blocks.
     fn get_data_commitment<const MAX_LEAVES: usize>(
         &mut self,
         data_hashes: &ArrayVariable<Bytes32Variable, MAX_LEAVES>,
@@ -37,11 +41,144 @@ pub trait DataCommitmentBuilder<L: PlonkParameters<D>, const D: usize> {
         end_block: U64Variable,
     ) -> Bytes32Variable;
 
+    /// Compute the data commitment over the first nb_leaves leaves abi.encode(block_heights[i], data_hashes[i]). Unlike
+    /// get_data_commitment, the heights don't need to be contiguous, so sparse or offset sets of blocks can be committed to.
+    /// nb_leaves <= MAX_LEAVES is asserted.
+    /// Note: This only matches Celestia's canonical data commitment if the enabled heights are contiguous and increasing.
+    fn get_data_commitment_with_heights<const MAX_LEAVES: usize>(
+        &mut self,
+        data_hashes: &ArrayVariable<Bytes32Variable, MAX_LEAVES>,
+        block_heights: &ArrayVariable<U64Variable, MAX_LEAVES>,
+        nb_leaves: U64Variable,
+    ) -> Bytes32Variable;
+
+    /// Compute the data commitment of the WINDOW_RANGE blocks [start_block, start_block + WINDOW_RANGE), whose data hashes are
+    /// data_hashes. WINDOW_RANGE doesn't need to be a power of two: the leaves are padded with disabled leaves up to MAX_LEAVES.
+    /// Note: MAX_LEAVES must equal WINDOW_RANGE.next_power_of_two(), which is checked at build time (it can't be derived from
+    /// WINDOW_RANGE, as array lengths can't depend on generic parameters).
+    fn get_data_commitment_for_window<const WINDOW_RANGE: usize, const MAX_LEAVES: usize>(
+        &mut self,
+        data_hashes: &ArrayVariable<Bytes32Variable, WINDOW_RANGE>,
+        start_block: U64Variable,
+    ) -> Bytes32Variable;
+
+    /// Compute the data commitment of the WINDOW_RANGE blocks [start_block, start_block + WINDOW_RANGE) over exactly
+    /// WINDOW_RANGE leaves, splitting each subtree at the largest power of two less than its number of leaves as RFC 6962 and
+    /// celestia-core do. Unlike get_data_commitment, no leaves are padded or masked, so the window size is fixed.
+    fn get_data_commitment_unpadded<const WINDOW_RANGE: usize>(
+        &mut self,
+        data_hashes: &ArrayVariable<Bytes32Variable, WINDOW_RANGE>,
+        start_block: U64Variable,
+    ) -> Bytes32Variable;
+
+    /// Compute a data commitment from start_block to end_block in which the leaves of the heights in excluded_heights are replaced
+    /// by the tuple (height, bytes32(0)). Excluded leaves keep their positions in the tree, so the roots of different exclusion sets
+    /// over the same range are comparable. Unused slots of excluded_heights can be set to any height outside the range (e.g. 0).
+    /// Note: This does NOT match Celestia's canonical data commitment if any height is excluded, and is only for custom applications.
+    fn get_data_commitment_excluding<const MAX_LEAVES: usize, const NB_EXCLUDED: usize>(
+        &mut self,
+        data_hashes: &ArrayVariable<Bytes32Variable, MAX_LEAVES>,
+        start_block: U64Variable,
+        end_block: U64Variable,
+        excluded_heights: &ArrayVariable<U64Variable, NB_EXCLUDED>,
+    ) -> Bytes32Variable;
+
+    /// Check a batch of NB_WINDOWS claimed data commitments against the data hashes of their windows. Window i covers
+    /// [start_blocks[i], end_blocks[i]) with data hashes window_data_hashes[i]. Returns whether all claimed roots are correct, and
+    /// whether each one is, so the failing windows can be output. Callers that need the proof to fail on any incorrect root should
+    /// assert the first return value is true.
+    fn prove_commitment_batch<const MAX_LEAVES: usize, const NB_WINDOWS: usize>(
+        &mut self,
+        window_data_hashes: &ArrayVariable<ArrayVariable<Bytes32Variable, MAX_LEAVES>, NB_WINDOWS>,
+        start_blocks: &ArrayVariable<U64Variable, NB_WINDOWS>,
+        end_blocks: &ArrayVariable<U64Variable, NB_WINDOWS>,
+        claimed_data_commitments: &ArrayVariable<Bytes32Variable, NB_WINDOWS>,
+    ) -> (BoolVariable, ArrayVariable<BoolVariable, NB_WINDOWS>);
+
+    /// Assert start_block <= end_block <= start_block + max_num_blocks, and return start_block + max_num_blocks.
+    /// max_num_blocks must be less than MAX_NUM_BLOCKS_BOUND, and start_block + max_num_blocks is checked to not wrap around,
+    /// so the comparisons don't rely on u64 wraparound semantics.
+    fn assert_valid_block_range(
+        &mut self,
+        start_block: U64Variable,
+        end_block: U64Variable,
+        max_num_blocks: usize,
+    ) -> U64Variable;
+
+    /// Assert the data hashes of the blocks in [start_block, end_block) are pairwise distinct.
+    /// Note: This is opt-in and is not called by the data commitment circuits, as empty blocks
+    /// legitimately share the same data hash. It is intended for debugging fetch bugs that
+    /// duplicate data hashes across a window.
+    fn assert_distinct_data_hashes<const MAX_LEAVES: usize>(
+        &mut self,
+        data_hashes: &ArrayVariable<Bytes32Variable, MAX_LEAVES>,
+        start_block: U64Variable,
+        end_block: U64Variable,
+    );
+
+    /// Assert at least min_nonempty of the data hashes in window_data_hashes are non-empty, i.e. nonzero. Zero data hashes are
+    /// never real block data hashes, so this counts the blocks in a window returned by prove_data_commitment_with_data_hashes,
+    /// whose data hashes at or after end_block are zero. Data availability monitors can use this to prove a range had activity.
+    fn assert_min_nonempty<const MAX_LEAVES: usize>(
+        &mut self,
+        window_data_hashes: &ArrayVariable<Bytes32Variable, MAX_LEAVES>,
+        min_nonempty: U32Variable,
+    );
+
+    /// Assert a new data commitment starting at new_start_block chains onto one of the K most
+    /// recent data commitments (i.e. new_start_block is the end_block of one of them), and return
+    /// the data commitment of the matching recent range.
+    /// Note: recent_end_blocks[i] is the end_block of the range committed to by recent_data_commitments[i].
+    fn assert_chains_onto_recent_commitment<const K: usize>(
+        &mut self,
+        recent_end_blocks: &ArrayVariable<U64Variable, K>,
+        recent_data_commitments: &ArrayVariable<Bytes32Variable, K>,
+        new_start_block: U64Variable,
+    ) -> Bytes32Variable;
+
+    /// Assert height is not in the data commitment range [start_block, end_block), i.e. height <
+    /// start_block or height >= end_block. Since a data commitment covers a contiguous range, this is
+    /// a proof of non-inclusion of height against the committed range bounds.
+    fn prove_height_not_in_range(
+        &mut self,
+        height: U64Variable,
+        start_block: U64Variable,
+        end_block: U64Variable,
+    );
+
+    /// Returns true if an external receipt (height, data_hash) is a leaf of data_commitment, the data commitment of
+    /// [start_block, end_block). proof holds the aunts of the receipt's leaf from the bottom of the tree up, and path[i] is bit i of
+    /// the leaf's index, which must equal height - start_block. The height must also be in [start_block, end_block).
+    /// Note: The proof must have exactly PROOF_DEPTH aunts, which is the case for every leaf if end_block - start_block is
+    /// 2^PROOF_DEPTH.
+    fn prove_receipt<const PROOF_DEPTH: usize>(
+        &mut self,
+        height: U64Variable,
+        data_hash: Bytes32Variable,
+        start_block: U64Variable,
+        end_block: U64Variable,
+        data_commitment: Bytes32Variable,
+        proof: &ArrayVariable<Bytes32Variable, PROOF_DEPTH>,
+        path: &ArrayVariable<BoolVariable, PROOF_DEPTH>,
+    ) -> BoolVariable;
+
+    /// Returns true if the protobuf-encoded last_block_id has a nonzero part_set_header total.
+    /// The nil BlockID (zero total, zero parts hash) is only the last_block_id of the genesis block,
+    /// and is never a valid link between two headers.
+    fn is_valid_last_block_id(
+        &mut self,
+        last_block_id: &BytesVariable<PROTOBUF_BLOCK_ID_SIZE_BYTES>,
+    ) -> BoolVariable;
+
     /// Verify the chain of headers is linked for the subrange in the data commitment proof & generate the subrange's data_merkle_root.
     /// Verify the header at global_end_block is the global_end_header_hash and don't include blocks after global_end_block in the merkle root computation.
     ///
     /// Specifically, a MapReduce circuit with <NB_MAP_JOBS=4, BATCH_SIZE=4> over blocks [0, 16) will invoke prove_subchain 4 times. Each of the 4 prove_subchain calls
     /// over [0, 4), [4, 8), [8, 12), [12, 16) will 1) prove the subchain of headers are linked and 2) output their corresponding data_merkle_root.
+    ///
+    /// Only the last_block_id's of blocks in (batch_start_block, global_end_block] are read, so the last_block_id of the first block in a range is never used.
+    /// This is what makes ranges starting at the genesis block (height 1) safe: block 1 has a null last_block_id (there is no block 0), whose protobuf encoding
+    /// is an empty hash and a default part_set_header rather than a header hash at [2..2+HASH_SIZE].
     fn prove_subchain<const BATCH_SIZE: usize>(
         &mut self,
         data_comm_proof: &DataCommitmentProofVariable<BATCH_SIZE>,
@@ -66,6 +203,66 @@ pub trait DataCommitmentBuilder<L: PlonkParameters<D>, const D: usize> {
         <<L as PlonkParameters<D>>::Config as GenericConfig<D>>::Hasher:
             AlgebraicHasher<<L as PlonkParameters<D>>::Field>;
 
+    /// Compute the data commitment for [start_block, end_block) twice and assert the roots are equal: once with the mapreduce path of
+    /// prove_data_commitment, and once by bagging the RFC 6962 peaks (see compute_data_commitment_by_peaks) of the data hashes
+    /// verified by prove_data_commitment_with_data_hashes. The two roots share no Merkle tree gadget, so a bug in one of them can't
+    /// silently produce an unsound commitment. This more than doubles the cost of the circuit, so it is only intended for
+    /// high-assurance deployments.
+    /// Note: MAX_LEAVES must equal NB_MAP_JOBS * BATCH_SIZE.
+    fn prove_data_commitment_redundant<
+        C: Circuit,
+        const NB_MAP_JOBS: usize,
+        const BATCH_SIZE: usize,
+        const MAX_LEAVES: usize,
+    >(
+        &mut self,
+        start_block: U64Variable,
+        start_header_hash: Bytes32Variable,
+        end_block: U64Variable,
+        end_header_hash: Bytes32Variable,
+    ) -> Bytes32Variable
+    where
+        <<L as PlonkParameters<D>>::Config as GenericConfig<D>>::Hasher:
+            AlgebraicHasher<<L as PlonkParameters<D>>::Field>;
+
+    /// Verify the chain of headers is linked from start_block to end_block, and return the data commitment along with the data hash of
+    /// each block in the window. Unlike prove_data_commitment, the whole window is proven in a single batch, so this is intended for
+    /// small windows.
+    /// Note: Data commitment is exclusive of end_block, and the data hashes of blocks at or after end_block are zero.
+    fn prove_data_commitment_with_data_hashes<const MAX_LEAVES: usize>(
+        &mut self,
+        start_block: U64Variable,
+        start_header_hash: Bytes32Variable,
+        end_block: U64Variable,
+        end_header_hash: Bytes32Variable,
+    ) -> (Bytes32Variable, ArrayVariable<Bytes32Variable, MAX_LEAVES>);
+
+    /// Verify the chain of headers extends BACK blocks below and FWD blocks above the trusted anchor header at anchor_block, and
+    /// return the linked range [anchor_block - BACK, anchor_block + FWD] as a MapReduceSubchainVariable, whose data_merkle_root is
+    /// the data commitment of [anchor_block - BACK, anchor_block + FWD). back_proof covers [anchor_block - BACK, anchor_block] and
+    /// ends at the anchor header, and fwd_proof covers [anchor_block, anchor_block + FWD] from the anchor header to high_header_hash.
+    /// Note: Hash links are only sound backwards from a trusted header, as anyone can build a header whose last_block_id is the
+    /// anchor's hash. So high_header_hash must also be trusted, e.g. the output of skip or step for anchor_block + FWD, and the
+    /// forward segment is verified backwards from it to the anchor.
+    /// Note: The data commitment is the inner node of the two segments' roots, so BACK must be a power of two and 0 < FWD <= BACK,
+    /// which makes BACK the split point of the RFC 6962 tree over the whole range.
+    fn prove_header_chain_bidirectional<const BACK: usize, const FWD: usize>(
+        &mut self,
+        anchor_block: U64Variable,
+        anchor_header_hash: Bytes32Variable,
+        high_header_hash: Bytes32Variable,
+        back_proof: &DataCommitmentProofVariable<BACK>,
+        fwd_proof: &DataCommitmentProofVariable<FWD>,
+    ) -> MapReduceSubchainVariable;
+
+    /// Verify and return the data hash of block_number, whose header hash is header_hash.
+    /// Note: block_number is only used to fetch the data hash proof, the data hash is bound to header_hash.
+    fn prove_data_hash(
+        &mut self,
+        block_number: U64Variable,
+        header_hash: Bytes32Variable,
+    ) -> Bytes32Variable;
+
     /// Prove the data commitment for the next header. This is a special case of prove_data_commitment where the range is always 1 block (only
     /// the prev header's data hash is included in the data commitment).
     /// Note: Assumes that prev_block_number and prev_header_hash are valid & linked. Specifically,
@@ -78,6 +275,167 @@ pub trait DataCommitmentBuilder<L: PlonkParameters<D>, const D: usize> {
     ) -> Bytes32Variable;
 }
 
+/// Compute the RFC 6962 root of the leaf hashes, splitting at the largest power of two less than the number of leaves.
+/// Note: Use sha256 instead of inner_hash to avoid allocating a Curta gadget.
+fn compute_rfc6962_root<L: PlonkParameters<D>, const D: usize>(
+    builder: &mut CircuitBuilder<L, D>,
+    leaf_hashes: &[Bytes32Variable],
+) -> Bytes32Variable {
+    if leaf_hashes.len() == 1 {
+        return leaf_hashes[0];
+    }
+    let split = leaf_hashes.len().next_power_of_two() / 2;
+    let left = compute_rfc6962_root(builder, &leaf_hashes[..split]);
+    let right = compute_rfc6962_root(builder, &leaf_hashes[split..]);
+
+    let one_byte = ByteVariable::constant(builder, 1u8);
+    let mut encoded_node = vec![one_byte];
+    encoded_node.extend(left.as_bytes().to_vec());
+    encoded_node.extend(right.as_bytes().to_vec());
+    builder.sha256(&encoded_node)
+}
+
+/// Compute the data commitment over the first nb_leaves data hashes, whose heights count up from start_block. Unlike get_data_commitment,
+/// which pads the tree to MAX_LEAVES leaves, this computes the RFC 6962 root for every leaf count in [1, MAX_LEAVES] by bagging the roots
+/// of its perfect subtrees, and selects the one for nb_leaves.
+/// Note: Bagging costs O(MAX_LEAVES * log(MAX_LEAVES)) sha256 in total, on top of the 2 * MAX_LEAVES sha256 for the perfect subtrees.
+pub(crate) fn compute_data_commitment_by_peaks<
+    L: PlonkParameters<D>,
+    const D: usize,
+    const MAX_LEAVES: usize,
+>(
+    builder: &mut CircuitBuilder<L, D>,
+    data_hashes: &ArrayVariable<Bytes32Variable, MAX_LEAVES>,
+    start_block: U64Variable,
+    nb_leaves: U64Variable,
+) -> Bytes32Variable {
+    assert!(MAX_LEAVES > 0, "MAX_LEAVES must be positive");
+
+    let zero_byte = ByteVariable::constant(builder, 0u8);
+    let one_byte = ByteVariable::constant(builder, 1u8);
+
+    // The leaf hash is sha256(0x00 || abi.encode(height, data_hash)).
+    let mut leaf_hashes = Vec::new();
+    for i in 0..MAX_LEAVES {
+        let curr_idx = builder.constant::<U64Variable>(i as u64);
+        let block_height = builder.add(start_block, curr_idx);
+        let encoded_tuple = builder.encode_data_root_tuple(&data_hashes[i], &block_height);
+        let mut encoded_leaf = vec![zero_byte];
+        encoded_leaf.extend(encoded_tuple.0.to_vec());
+        leaf_hashes.push(builder.sha256(&encoded_leaf));
+    }
+
+    // subtree_roots[l][j] is the root of the perfect subtree over leaves [j * 2^l, (j + 1) * 2^l).
+    let mut subtree_roots = vec![leaf_hashes];
+    while subtree_roots[subtree_roots.len() - 1].len() > 1 {
+        let level = &subtree_roots[subtree_roots.len() - 1];
+        let mut next_level = Vec::new();
+        for pair in level.chunks_exact(2) {
+            let mut encoded_node = vec![one_byte];
+            encoded_node.extend(pair[0].as_bytes().to_vec());
+            encoded_node.extend(pair[1].as_bytes().to_vec());
+            next_level.push(builder.sha256(&encoded_node));
+        }
+        subtree_roots.push(next_level);
+    }
+
+    // The RFC 6962 root over [offset, offset + len) splits at the largest power of two less than len, so its left child is always a
+    // perfect subtree, and offset is always a multiple of the size of the perfect subtrees it is split into.
+    fn bag_peaks<L: PlonkParameters<D>, const D: usize>(
+        builder: &mut CircuitBuilder<L, D>,
+        subtree_roots: &[Vec<Bytes32Variable>],
+        one_byte: ByteVariable,
+        offset: usize,
+        len: usize,
+    ) -> Bytes32Variable {
+        if len.is_power_of_two() {
+            return subtree_roots[len.trailing_zeros() as usize][offset / len];
+        }
+        let split = len.next_power_of_two() / 2;
+        let left = subtree_roots[split.trailing_zeros() as usize][offset / split];
+        let right = bag_peaks(
+            builder,
+            subtree_roots,
+            one_byte,
+            offset + split,
+            len - split,
+        );
+
+        let mut encoded_node = vec![one_byte];
+        encoded_node.extend(left.as_bytes().to_vec());
+        encoded_node.extend(right.as_bytes().to_vec());
+        builder.sha256(&encoded_node)
+    }
+
+    // Select the root for nb_leaves, and assert nb_leaves is in [1, MAX_LEAVES].
+    let mut data_commitment = subtree_roots[0][0];
+    let mut is_valid_nb_leaves = builder._false();
+    for nb in 1..=MAX_LEAVES {
+        let nb_v = builder.constant::<U64Variable>(nb as u64);
+        let is_nb_leaves = builder.is_equal(nb_leaves, nb_v);
+        let root = bag_peaks(builder, &subtree_roots, one_byte, 0, nb);
+        data_commitment = builder.select(is_nb_leaves, root, data_commitment);
+        is_valid_nb_leaves = builder.or(is_valid_nb_leaves, is_nb_leaves);
+    }
+    let true_v = builder._true();
+    builder.assert_is_equal(is_valid_nb_leaves, true_v);
+
+    data_commitment
+}
+
+/// Implements prove_data_commitment_redundant, with the computation of the second data commitment passed in as
+/// independent_data_commitment(builder, data_hashes, start_block, nb_leaves), so tests can check a divergent path fails the proof.
+pub(crate) fn prove_data_commitment_redundant_with<
+    L: PlonkParameters<D>,
+    const D: usize,
+    C: Circuit,
+    const NB_MAP_JOBS: usize,
+    const BATCH_SIZE: usize,
+    const MAX_LEAVES: usize,
+>(
+    builder: &mut CircuitBuilder<L, D>,
+    start_block: U64Variable,
+    start_header_hash: Bytes32Variable,
+    end_block: U64Variable,
+    end_header_hash: Bytes32Variable,
+    independent_data_commitment: impl FnOnce(
+        &mut CircuitBuilder<L, D>,
+        &ArrayVariable<Bytes32Variable, MAX_LEAVES>,
+        U64Variable,
+        U64Variable,
+    ) -> Bytes32Variable,
+) -> Bytes32Variable
+where
+    <<L as PlonkParameters<D>>::Config as GenericConfig<D>>::Hasher:
+        AlgebraicHasher<<L as PlonkParameters<D>>::Field>,
+{
+    assert!(
+        MAX_LEAVES == NB_MAP_JOBS * BATCH_SIZE,
+        "MAX_LEAVES must equal NB_MAP_JOBS * BATCH_SIZE"
+    );
+
+    let mapreduce_data_commitment = builder.prove_data_commitment::<C, NB_MAP_JOBS, BATCH_SIZE>(
+        start_block,
+        start_header_hash,
+        end_block,
+        end_header_hash,
+    );
+
+    // The data hashes are verified against the chain of headers from start_header_hash to end_header_hash.
+    let (_, data_hashes) = builder.prove_data_commitment_with_data_hashes::<MAX_LEAVES>(
+        start_block,
+        start_header_hash,
+        end_block,
+        end_header_hash,
+    );
+    let nb_leaves = builder.sub(end_block, start_block);
+    let data_commitment =
+        independent_data_commitment(builder, &data_hashes, start_block, nb_leaves);
+    builder.assert_is_equal(mapreduce_data_commitment, data_commitment);
+
+    mapreduce_data_commitment
+}
+
 impl<L: PlonkParameters<D>, const D: usize> DataCommitmentBuilder<L, D> for CircuitBuilder<L, D> {
     fn encode_data_root_tuple(
         &mut self,
@@ -108,45 +466,338 @@ impl<L: PlonkParameters<D>, const D: usize> DataCommitmentBuilder<L, D> for Circ
         start_block: U64Variable,
         end_block: U64Variable,
     ) -> Bytes32Variable {
-        let true_var = self._true();
-        // Assert end_block >= start_block.
-        let end_block_gte_start_block = self.gte(end_block, start_block);
-        self.assert_is_equal(end_block_gte_start_block, true_var);
+        assert!(
+            MAX_LEAVES < MAX_NUM_BLOCKS_BOUND,
+            "MAX_LEAVES must be < MAX_NUM_BLOCKS_BOUND"
+        );
+
+        // Assert start_block <= end_block <= start_block + MAX_LEAVES. compute_root_from_leaves treats any number of enabled
+        // leaves above MAX_LEAVES as MAX_LEAVES, so a longer range would otherwise silently commit to a truncated range.
+        self.assert_valid_block_range(start_block, end_block, MAX_LEAVES);
 
-        // If end_block < start_block, then this data commitment will be marked as disabled, and the
-        // output of this function is not used. Therefore, the logic assumes
-        // nb_blocks is always positive.
         let nb_blocks_in_batch = self.sub(end_block, start_block);
 
-        // Note: nb_blocks_in_batch is assumed to be less than 2^32 (which is a reasonable
-        // assumption for any data commitment as in practice, the number of blocks in a data
-        // commitment range will be much smaller than 2^32).
-        let nb_enabled_leaves = nb_blocks_in_batch.limbs[0].variable;
-        let zero = self.zero();
+        let mut block_heights = Vec::new();
+        for i in 0..MAX_LEAVES {
+            let curr_idx = self.constant::<U64Variable>(i as u64);
+            block_heights.push(self.add(start_block, curr_idx));
+        }
+
+        self.get_data_commitment_with_heights::<MAX_LEAVES>(
+            data_hashes,
+            &ArrayVariable::<U64Variable, MAX_LEAVES>::from(block_heights),
+            nb_blocks_in_batch,
+        )
+    }
+
+    fn get_data_commitment_with_heights<const MAX_LEAVES: usize>(
+        &mut self,
+        data_hashes: &ArrayVariable<Bytes32Variable, MAX_LEAVES>,
+        block_heights: &ArrayVariable<U64Variable, MAX_LEAVES>,
+        nb_leaves: U64Variable,
+    ) -> Bytes32Variable {
+        assert!(
+            MAX_LEAVES < MAX_NUM_BLOCKS_BOUND,
+            "MAX_LEAVES must be < MAX_NUM_BLOCKS_BOUND"
+        );
+
+        // Assert nb_leaves <= MAX_LEAVES, so the leaves after MAX_LEAVES are never silently dropped.
+        let true_var = self._true();
+        let max_leaves = self.constant::<U64Variable>(MAX_LEAVES as u64);
+        let nb_leaves_check = self.lte(nb_leaves, max_leaves);
+        self.assert_is_equal(nb_leaves_check, true_var);
 
-        // Constrain nb_blocks_in_batch.limbs[1] to be zero. (i.e. nb_blocks_in_batch < 2^32)
-        self.assert_is_equal(nb_blocks_in_batch.limbs[1], zero);
+        // nb_leaves <= MAX_LEAVES < 2^32, so it is equal to its lower limb.
+        let nb_enabled_leaves = nb_leaves.limbs[0].variable;
 
         let mut leaves = Vec::new();
 
         // Compute the leaves of the merkle tree.
         for i in 0..MAX_LEAVES {
-            let curr_idx = self.constant::<U64Variable>(i as u64);
-            let block_height = self.add(start_block, curr_idx);
-
             // Each leaf in Blobstream is abi.encodePacked(height, data_hash).
-            leaves.push(self.encode_data_root_tuple(&data_hashes[i], &block_height));
+            leaves.push(self.encode_data_root_tuple(&data_hashes[i], &block_heights[i]));
         }
 
-        // Compute the root of the merkle tree over the first num_blocks leaves.
-        // Note: If nb_enabled_leaves is larger than MAX_LEAVES, this function will
-        // mark all leaves as enabled and compute the root of the merkle tree over all leaves.
+        // Compute the root of the merkle tree over the first nb_leaves leaves.
         self.compute_root_from_leaves::<MAX_LEAVES, ENC_DATA_ROOT_TUPLE_SIZE_BYTES>(
             ArrayVariable::<BytesVariable<64>, MAX_LEAVES>::from(leaves),
             nb_enabled_leaves,
         )
     }
 
+    fn get_data_commitment_for_window<const WINDOW_RANGE: usize, const MAX_LEAVES: usize>(
+        &mut self,
+        data_hashes: &ArrayVariable<Bytes32Variable, WINDOW_RANGE>,
+        start_block: U64Variable,
+    ) -> Bytes32Variable {
+        assert!(WINDOW_RANGE > 0, "WINDOW_RANGE must be positive");
+        assert!(
+            MAX_LEAVES == WINDOW_RANGE.next_power_of_two(),
+            "MAX_LEAVES must equal WINDOW_RANGE.next_power_of_two()"
+        );
+
+        // The padding leaves are after end_block, so they are disabled in the data commitment.
+        let zero_hash = self.constant::<Bytes32Variable>(H256::zero());
+        let mut padded_data_hashes = data_hashes.data.clone();
+        padded_data_hashes.resize(MAX_LEAVES, zero_hash);
+
+        let window_range = self.constant::<U64Variable>(WINDOW_RANGE as u64);
+        let end_block = self.add(start_block, window_range);
+        self.get_data_commitment::<MAX_LEAVES>(
+            &ArrayVariable::<Bytes32Variable, MAX_LEAVES>::from(padded_data_hashes),
+            start_block,
+            end_block,
+        )
+    }
+
+    fn get_data_commitment_unpadded<const WINDOW_RANGE: usize>(
+        &mut self,
+        data_hashes: &ArrayVariable<Bytes32Variable, WINDOW_RANGE>,
+        start_block: U64Variable,
+    ) -> Bytes32Variable {
+        assert!(WINDOW_RANGE > 0, "WINDOW_RANGE must be positive");
+
+        let zero_byte = ByteVariable::constant(self, 0u8);
+        let mut leaf_hashes = Vec::new();
+        for i in 0..WINDOW_RANGE {
+            let curr_idx = self.constant::<U64Variable>(i as u64);
+            let block_height = self.add(start_block, curr_idx);
+
+            // The leaf hash is sha256(0x00 || abi.encode(height, data_hash)).
+            let encoded_tuple = self.encode_data_root_tuple(&data_hashes[i], &block_height);
+            let mut encoded_leaf = vec![zero_byte];
+            encoded_leaf.extend(encoded_tuple.0.to_vec());
+            leaf_hashes.push(self.sha256(&encoded_leaf));
+        }
+
+        compute_rfc6962_root(self, &leaf_hashes)
+    }
+
+    fn get_data_commitment_excluding<const MAX_LEAVES: usize, const NB_EXCLUDED: usize>(
+        &mut self,
+        data_hashes: &ArrayVariable<Bytes32Variable, MAX_LEAVES>,
+        start_block: U64Variable,
+        end_block: U64Variable,
+        excluded_heights: &ArrayVariable<U64Variable, NB_EXCLUDED>,
+    ) -> Bytes32Variable {
+        let zero_hash = self.constant::<Bytes32Variable>(H256::zero());
+
+        let mut masked_data_hashes = Vec::new();
+        for i in 0..MAX_LEAVES {
+            let curr_idx = self.constant::<U64Variable>(i as u64);
+            let block_height = self.add(start_block, curr_idx);
+
+            let mut is_excluded = self._false();
+            for j in 0..NB_EXCLUDED {
+                let is_curr_excluded = self.is_equal(block_height, excluded_heights[j]);
+                is_excluded = self.or(is_excluded, is_curr_excluded);
+            }
+            masked_data_hashes.push(self.select(is_excluded, zero_hash, data_hashes[i]));
+        }
+
+        self.get_data_commitment::<MAX_LEAVES>(
+            &ArrayVariable::<Bytes32Variable, MAX_LEAVES>::from(masked_data_hashes),
+            start_block,
+            end_block,
+        )
+    }
+
+    fn prove_commitment_batch<const MAX_LEAVES: usize, const NB_WINDOWS: usize>(
+        &mut self,
+        window_data_hashes: &ArrayVariable<ArrayVariable<Bytes32Variable, MAX_LEAVES>, NB_WINDOWS>,
+        start_blocks: &ArrayVariable<U64Variable, NB_WINDOWS>,
+        end_blocks: &ArrayVariable<U64Variable, NB_WINDOWS>,
+        claimed_data_commitments: &ArrayVariable<Bytes32Variable, NB_WINDOWS>,
+    ) -> (BoolVariable, ArrayVariable<BoolVariable, NB_WINDOWS>) {
+        let mut all_valid = self._true();
+        let mut is_window_valid = Vec::new();
+
+        for i in 0..NB_WINDOWS {
+            let data_commitment = self.get_data_commitment::<MAX_LEAVES>(
+                &window_data_hashes[i],
+                start_blocks[i],
+                end_blocks[i],
+            );
+            let is_valid = self.is_equal(data_commitment, claimed_data_commitments[i]);
+            all_valid = self.and(all_valid, is_valid);
+            is_window_valid.push(is_valid);
+        }
+
+        (all_valid, ArrayVariable::from(is_window_valid))
+    }
+
+    fn assert_valid_block_range(
+        &mut self,
+        start_block: U64Variable,
+        end_block: U64Variable,
+        max_num_blocks: usize,
+    ) -> U64Variable {
+        assert!(
+            max_num_blocks < MAX_NUM_BLOCKS_BOUND,
+            "max_num_blocks must be < MAX_NUM_BLOCKS_BOUND"
+        );
+
+        let true_v = self._true();
+        let max_num_blocks_v = self.constant::<U64Variable>(max_num_blocks as u64);
+        let start_plus_max_num_blocks = self.add(start_block, max_num_blocks_v);
+
+        // Assert start_block + max_num_blocks did not wrap around.
+        let no_overflow_check = self.gte(start_plus_max_num_blocks, start_block);
+        self.assert_is_equal(no_overflow_check, true_v);
+
+        // Assert start_block <= end_block <= start_block + max_num_blocks.
+        let start_block_check = self.lte(start_block, end_block);
+        self.assert_is_equal(start_block_check, true_v);
+        let end_block_check = self.lte(end_block, start_plus_max_num_blocks);
+        self.assert_is_equal(end_block_check, true_v);
+
+        start_plus_max_num_blocks
+    }
+
+    fn assert_distinct_data_hashes<const MAX_LEAVES: usize>(
+        &mut self,
+        data_hashes: &ArrayVariable<Bytes32Variable, MAX_LEAVES>,
+        start_block: U64Variable,
+        end_block: U64Variable,
+    ) {
+        let true_var = self._true();
+        let nb_blocks = self.sub(end_block, start_block);
+
+        for j in 1..MAX_LEAVES {
+            // Leaves at or past nb_blocks are padding, so duplicates there are ignored.
+            let curr_idx = self.constant::<U64Variable>(j as u64);
+            let is_leaf_enabled = self.lt(curr_idx, nb_blocks);
+            let is_leaf_disabled = self.not(is_leaf_enabled);
+
+            for i in 0..j {
+                let is_duplicate = self.is_equal(data_hashes[i], data_hashes[j]);
+                let is_distinct = self.not(is_duplicate);
+                let distinct_check = self.or(is_leaf_disabled, is_distinct);
+                self.assert_is_equal(distinct_check, true_var);
+            }
+        }
+    }
+
+    fn assert_min_nonempty<const MAX_LEAVES: usize>(
+        &mut self,
+        window_data_hashes: &ArrayVariable<Bytes32Variable, MAX_LEAVES>,
+        min_nonempty: U32Variable,
+    ) {
+        let true_var = self._true();
+        let zero = self.constant::<U32Variable>(0);
+        let one = self.constant::<U32Variable>(1);
+
+        let mut nb_nonempty = zero;
+        for i in 0..MAX_LEAVES {
+            let is_empty = self.is_zero_bytes32(window_data_hashes[i]);
+            let increment = self.select(is_empty, zero, one);
+            nb_nonempty = self.add(nb_nonempty, increment);
+        }
+
+        let has_min_nonempty = self.gte(nb_nonempty, min_nonempty);
+        self.assert_is_equal(has_min_nonempty, true_var);
+    }
+
+    fn assert_chains_onto_recent_commitment<const K: usize>(
+        &mut self,
+        recent_end_blocks: &ArrayVariable<U64Variable, K>,
+        recent_data_commitments: &ArrayVariable<Bytes32Variable, K>,
+        new_start_block: U64Variable,
+    ) -> Bytes32Variable {
+        let true_var = self._true();
+        let mut is_chained = self._false();
+        let mut matched_data_commitment = recent_data_commitments[0];
+
+        for i in 0..K {
+            let is_curr_match = self.is_equal(recent_end_blocks[i], new_start_block);
+            matched_data_commitment = self.select(
+                is_curr_match,
+                recent_data_commitments[i],
+                matched_data_commitment,
+            );
+            is_chained = self.or(is_chained, is_curr_match);
+        }
+
+        // Assert new_start_block matched at least one of the recent end blocks.
+        self.assert_is_equal(is_chained, true_var);
+
+        matched_data_commitment
+    }
+
+    fn prove_height_not_in_range(
+        &mut self,
+        height: U64Variable,
+        start_block: U64Variable,
+        end_block: U64Variable,
+    ) {
+        let true_var = self._true();
+
+        let is_before_range = self.lt(height, start_block);
+        let is_after_range = self.gte(height, end_block);
+        let is_not_in_range = self.or(is_before_range, is_after_range);
+        self.assert_is_equal(is_not_in_range, true_var);
+    }
+
+    fn prove_receipt<const PROOF_DEPTH: usize>(
+        &mut self,
+        height: U64Variable,
+        data_hash: Bytes32Variable,
+        start_block: U64Variable,
+        end_block: U64Variable,
+        data_commitment: Bytes32Variable,
+        proof: &ArrayVariable<Bytes32Variable, PROOF_DEPTH>,
+        path: &ArrayVariable<BoolVariable, PROOF_DEPTH>,
+    ) -> BoolVariable {
+        let receipt_proof =
+            MerkleInclusionProofVariable::<PROOF_DEPTH, ENC_DATA_ROOT_TUPLE_SIZE_BYTES> {
+                proof: proof.clone(),
+                leaf: self.encode_data_root_tuple(&data_hash, &height),
+            };
+        let receipt_root = self
+            .get_root_from_merkle_proof::<PROOF_DEPTH, ENC_DATA_ROOT_TUPLE_SIZE_BYTES>(
+                &receipt_proof,
+                path,
+            );
+        let is_root_valid = self.is_equal(receipt_root, data_commitment);
+
+        // Reconstruct the leaf index from the path, so a proof for another leaf can't be paired with this height.
+        let zero = self.constant::<U64Variable>(0);
+        let mut leaf_index = zero;
+        for i in 0..PROOF_DEPTH {
+            let bit_value = self.constant::<U64Variable>(1 << i);
+            let term = self.select(path[i], bit_value, zero);
+            leaf_index = self.add(leaf_index, term);
+        }
+        let leaf_height = self.add(start_block, leaf_index);
+        let is_index_valid = self.is_equal(leaf_height, height);
+
+        let is_after_start = self.gte(height, start_block);
+        let is_before_end = self.lt(height, end_block);
+        let is_in_range = self.and(is_after_start, is_before_end);
+
+        let is_leaf_valid = self.and(is_root_valid, is_index_valid);
+        self.and(is_leaf_valid, is_in_range)
+    }
+
+    fn is_valid_last_block_id(
+        &mut self,
+        last_block_id: &BytesVariable<PROTOBUF_BLOCK_ID_SIZE_BYTES>,
+    ) -> BoolVariable {
+        // proto3 omits a zero total, so a canonical encoding with a nonzero total has the total's
+        // tag at BLOCK_ID_PARTS_TOTAL_TAG_INDEX followed by a nonzero byte.
+        let parts_total_tag = self.constant::<ByteVariable>(0x08);
+        let zero_byte = self.constant::<ByteVariable>(0);
+
+        let has_parts_total_tag = self.is_equal(
+            last_block_id[BLOCK_ID_PARTS_TOTAL_TAG_INDEX],
+            parts_total_tag,
+        );
+        let is_parts_total_zero =
+            self.is_equal(last_block_id[BLOCK_ID_PARTS_TOTAL_INDEX], zero_byte);
+        let is_parts_total_nonzero = self.not(is_parts_total_zero);
+        self.and(has_parts_total_tag, is_parts_total_nonzero)
+    }
+
     fn prove_subchain<const BATCH_SIZE: usize>(
         &mut self,
         data_comm_proof: &DataCommitmentProofVariable<BATCH_SIZE>,
@@ -206,6 +857,13 @@ impl<L: PlonkParameters<D>, const D: usize> DataCommitmentBuilder<L, D> for Circ
             let prev_header_check = self.or(curr_block_disabled, is_valid_prev_header);
             self.assert_is_equal(prev_header_check, true_bool);
 
+            // Verify the last_block_id linking block curr_idx to curr_idx+1 is not the nil BlockID.
+            // The genesis block's nil last_block_id is never read (see prove_subchain's doc comment).
+            let is_valid_last_block_id =
+                self.is_valid_last_block_id(&data_comm_proof.last_block_id_proofs[i].leaf);
+            let last_block_id_check = self.or(curr_block_disabled, is_valid_last_block_id);
+            self.assert_is_equal(last_block_id_check, true_bool);
+
             // Verify the data hash proof is valid against block curr_idx.
             let is_data_hash_proof_valid = self.is_equal(data_hash_proof_root, header_hash.into());
             let data_hash_check = self.or(curr_block_disabled, is_data_hash_proof_valid);
@@ -289,12 +947,8 @@ impl<L: PlonkParameters<D>, const D: usize> DataCommitmentBuilder<L, D> for Circ
         };
 
         let max_num_blocks = NB_MAP_JOBS * BATCH_SIZE;
-        // Assert end_block <= start_block + NB_MAP_JOBS * BATCH_SIZE.
-        let true_v = self._true();
-        let max_num_blocks_v = self.constant::<U64Variable>(max_num_blocks as u64);
-        let start_plus_max_num_blocks = self.add(start_block, max_num_blocks_v);
-        let end_block_check = self.lte(end_block, start_plus_max_num_blocks);
-        self.assert_is_equal(end_block_check, true_v);
+        // Assert start_block <= end_block <= start_block + NB_MAP_JOBS * BATCH_SIZE.
+        self.assert_valid_block_range(start_block, end_block, max_num_blocks);
 
         let relative_block_nums = (0u64..(max_num_blocks as u64)).collect::<Vec<_>>();
 
@@ -408,33 +1062,198 @@ impl<L: PlonkParameters<D>, const D: usize> DataCommitmentBuilder<L, D> for Circ
         result.data_merkle_root
     }
 
-    fn prove_next_header_data_commitment(
+    fn prove_data_commitment_redundant<
+        C: Circuit,
+        const NB_MAP_JOBS: usize,
+        const BATCH_SIZE: usize,
+        const MAX_LEAVES: usize,
+    >(
         &mut self,
-        prev_block_number: U64Variable,
-        prev_header_hash: Bytes32Variable,
-        next_block_number: U64Variable,
-    ) -> Bytes32Variable {
-        // Compute data commitment (always for 1 leaf).
+        start_block: U64Variable,
+        start_header_hash: Bytes32Variable,
+        end_block: U64Variable,
+        end_header_hash: Bytes32Variable,
+    ) -> Bytes32Variable
+    where
+        <<L as PlonkParameters<D>>::Config as GenericConfig<D>>::Hasher:
+            AlgebraicHasher<<L as PlonkParameters<D>>::Field>,
+    {
+        prove_data_commitment_redundant_with::<L, D, C, NB_MAP_JOBS, BATCH_SIZE, MAX_LEAVES>(
+            self,
+            start_block,
+            start_header_hash,
+            end_block,
+            end_header_hash,
+            compute_data_commitment_by_peaks::<L, D, MAX_LEAVES>,
+        )
+    }
+
+    fn prove_data_commitment_with_data_hashes<const MAX_LEAVES: usize>(
+        &mut self,
+        start_block: U64Variable,
+        start_header_hash: Bytes32Variable,
+        end_block: U64Variable,
+        end_header_hash: Bytes32Variable,
+    ) -> (Bytes32Variable, ArrayVariable<Bytes32Variable, MAX_LEAVES>) {
+        // Assert start_block <= end_block <= start_block + MAX_LEAVES.
+        let window_end_block = self.assert_valid_block_range(start_block, end_block, MAX_LEAVES);
+
+        // Fetch and read the data commitment inputs for the window.
         let mut input_stream = VariableStream::new();
-        input_stream.write(&prev_block_number);
-        input_stream.write(&next_block_number);
-        let data_comm_fetcher = DataCommitmentOffchainInputs::<1> {};
+        input_stream.write(&start_block);
+        input_stream.write(&end_block);
+        let data_comm_fetcher = DataCommitmentOffchainInputs::<MAX_LEAVES> {};
         let output_stream = self.async_hint(input_stream, data_comm_fetcher);
-        let data_comm_proof = output_stream.read::<DataCommitmentProofVariable<1>>(self);
-
-        // Path of the data_hash against the Tendermint header.
-        let data_hash_path =
-            self.constant::<ArrayVariable<BoolVariable, 4>>(vec![false, true, true, false]);
-        // Confirm the data_comm_proof corresponds to the prev_header_hash.
-        let data_hash_proof_root = self
-            .get_root_from_merkle_proof::<HEADER_PROOF_DEPTH, PROTOBUF_HASH_SIZE_BYTES>(
-                &data_comm_proof.data_hash_proofs[0],
-                &data_hash_path,
-            );
-        self.assert_is_equal(data_hash_proof_root, prev_header_hash);
+        let data_comm_proof = output_stream.read::<DataCommitmentProofVariable<MAX_LEAVES>>(self);
 
-        let leaf =
-            Bytes32Variable::from(&data_comm_proof.data_hash_proofs.data[0].leaf[2..2 + HASH_SIZE]);
+        // Verify the chain of headers is linked for the window & compute the data commitment.
+        let subchain = self.prove_subchain(
+            &data_comm_proof,
+            start_block,
+            window_end_block,
+            end_block,
+            end_header_hash,
+        );
+        self.assert_is_equal(subchain.start_header, start_header_hash);
+        self.assert_is_equal(subchain.end_block, end_block);
+        self.assert_is_equal(subchain.end_header, end_header_hash);
+
+        // The data hashes of blocks at or after end_block are not in the data commitment, so zero them.
+        let zero_hash = self.constant::<Bytes32Variable>(H256::zero());
+        let mut data_hashes = Vec::new();
+        for i in 0..MAX_LEAVES {
+            let curr_idx = self.constant::<U64Variable>(i as u64);
+            let block_height = self.add(start_block, curr_idx);
+            let is_block_enabled = self.lt(block_height, end_block);
+
+            let data_hash =
+                Bytes32Variable::from(&data_comm_proof.data_hash_proofs[i].leaf[2..2 + HASH_SIZE]);
+            data_hashes.push(self.select(is_block_enabled, data_hash, zero_hash));
+        }
+
+        (
+            subchain.data_merkle_root,
+            ArrayVariable::<Bytes32Variable, MAX_LEAVES>::from(data_hashes),
+        )
+    }
+
+    fn prove_header_chain_bidirectional<const BACK: usize, const FWD: usize>(
+        &mut self,
+        anchor_block: U64Variable,
+        anchor_header_hash: Bytes32Variable,
+        high_header_hash: Bytes32Variable,
+        back_proof: &DataCommitmentProofVariable<BACK>,
+        fwd_proof: &DataCommitmentProofVariable<FWD>,
+    ) -> MapReduceSubchainVariable {
+        assert!(BACK.is_power_of_two(), "BACK must be a power of two");
+        assert!(FWD > 0 && FWD <= BACK, "FWD must be in [1, BACK]");
+
+        let true_bool = self._true();
+
+        // The lowest block is anchor_block - BACK, which must be at least the genesis block (height 1).
+        let back_v = self.constant::<U64Variable>(BACK as u64);
+        let is_low_block_valid = self.lt(back_v, anchor_block);
+        self.assert_is_equal(is_low_block_valid, true_bool);
+        let low_block = self.sub(anchor_block, back_v);
+
+        // The highest block is anchor_block + FWD, which is checked to not wrap around.
+        let fwd_v = self.constant::<U64Variable>(FWD as u64);
+        let high_block = self.add(anchor_block, fwd_v);
+        self.assert_valid_block_range(anchor_block, high_block, FWD);
+
+        // Verify the backward chain is linked from low_block up to the anchor header.
+        let back_subchain = self.prove_subchain::<BACK>(
+            back_proof,
+            low_block,
+            anchor_block,
+            anchor_block,
+            anchor_header_hash,
+        );
+
+        // Verify the forward chain is linked from the anchor header up to the trusted high header. The high header must be trusted
+        // (rather than fwd_proof.end_header, which is a witness), so the links are verified backwards from it.
+        self.assert_is_equal(fwd_proof.start_header, anchor_header_hash);
+        let fwd_subchain = self.prove_subchain::<FWD>(
+            fwd_proof,
+            anchor_block,
+            high_block,
+            high_block,
+            high_header_hash,
+        );
+
+        // The backward segment is the left subtree of the data commitment, and the forward segment is the right subtree.
+        let one_byte = ByteVariable::constant(self, 1u8);
+        let mut encoded_node = vec![one_byte];
+        encoded_node.extend(back_subchain.data_merkle_root.as_bytes().to_vec());
+        encoded_node.extend(fwd_subchain.data_merkle_root.as_bytes().to_vec());
+        // Note: Use sha256 instead of inner_hash to avoid allocating a Curta gadget.
+        let data_merkle_root = self.sha256(&encoded_node);
+
+        MapReduceSubchainVariable {
+            is_enabled: true_bool,
+            start_block: low_block,
+            start_header: back_subchain.start_header,
+            end_block: high_block,
+            end_header: fwd_subchain.end_header,
+            data_merkle_root,
+        }
+    }
+
+    fn prove_data_hash(
+        &mut self,
+        block_number: U64Variable,
+        header_hash: Bytes32Variable,
+    ) -> Bytes32Variable {
+        // Fetch and read the data hash proof of the block.
+        let mut input_stream = VariableStream::new();
+        input_stream.write(&block_number);
+        let output_stream = self.async_hint(input_stream, DataHashProofOffchainInputs {});
+        let data_hash_proof = output_stream
+            .read::<MerkleInclusionProofVariable<HEADER_PROOF_DEPTH, PROTOBUF_HASH_SIZE_BYTES>>(
+                self,
+            );
+
+        // Path of the data_hash against the Tendermint header.
+        let data_hash_path =
+            self.constant::<ArrayVariable<BoolVariable, 4>>(vec![false, true, true, false]);
+        // Confirm the data_hash_proof corresponds to the header_hash.
+        let data_hash_proof_root = self
+            .get_root_from_merkle_proof::<HEADER_PROOF_DEPTH, PROTOBUF_HASH_SIZE_BYTES>(
+                &data_hash_proof,
+                &data_hash_path,
+            );
+        self.assert_is_equal(data_hash_proof_root, header_hash);
+
+        Bytes32Variable::from(&data_hash_proof.leaf[2..2 + HASH_SIZE])
+    }
+
+    fn prove_next_header_data_commitment(
+        &mut self,
+        prev_block_number: U64Variable,
+        prev_header_hash: Bytes32Variable,
+        next_block_number: U64Variable,
+    ) -> Bytes32Variable {
+        // Compute data commitment (always for 1 leaf).
+        let mut input_stream = VariableStream::new();
+        input_stream.write(&prev_block_number);
+        input_stream.write(&next_block_number);
+        let data_comm_fetcher = DataCommitmentOffchainInputs::<1> {};
+        let output_stream = self.async_hint(input_stream, data_comm_fetcher);
+        let data_comm_proof = output_stream.read::<DataCommitmentProofVariable<1>>(self);
+
+        // Path of the data_hash against the Tendermint header.
+        let data_hash_path =
+            self.constant::<ArrayVariable<BoolVariable, 4>>(vec![false, true, true, false]);
+        // Confirm the data_comm_proof corresponds to the prev_header_hash.
+        let data_hash_proof_root = self
+            .get_root_from_merkle_proof::<HEADER_PROOF_DEPTH, PROTOBUF_HASH_SIZE_BYTES>(
+                &data_comm_proof.data_hash_proofs[0],
+                &data_hash_path,
+            );
+        self.assert_is_equal(data_hash_proof_root, prev_header_hash);
+
+        let leaf =
+            Bytes32Variable::from(&data_comm_proof.data_hash_proofs.data[0].leaf[2..2 + HASH_SIZE]);
 
         let encoded_tuple = self.encode_data_root_tuple(&leaf, &prev_block_number);
 
@@ -447,12 +1266,18 @@ impl<L: PlonkParameters<D>, const D: usize> DataCommitmentBuilder<L, D> for Circ
 // Alternatively, add env::set_var("RUST_LOG", "debug") to the top of the test.
 #[cfg(test)]
 pub(crate) mod tests {
-    use ethers::types::H256;
+    use ethers::abi::Token;
+    use ethers::types::U256;
+    use plonky2x::backend::circuit::CircuitBuild;
+    use sha2::{Digest, Sha256};
+    use tendermint::block::{Header, Id as BlockId};
+    use tendermint_proto::Protobuf;
     use tendermintx::input::InputDataFetcher;
     use tokio::runtime::Runtime;
 
     use super::*;
-    use crate::input::DataCommitmentInputFetcher;
+    use crate::input::{header_inclusion_proof, DataCommitmentInputFetcher};
+    use crate::utils::Hash32;
     use crate::vars::*;
 
     type L = DefaultParameters;
@@ -472,16 +1297,17 @@ pub(crate) mod tests {
             input_data_fetcher
                 .get_data_commitment_inputs::<MAX_LEAVES, F>(start_height as u64, end_height as u64)
                 .await
+                .unwrap()
         });
 
         (
             DataCommitmentProofValueType {
-                start_header: H256::from_slice(&result.start_header_hash),
-                end_header: H256::from_slice(&result.end_header_hash),
+                start_header: result.start_header_hash.into(),
+                end_header: result.end_header_hash.into(),
                 data_hash_proofs: result.data_hash_proofs,
                 last_block_id_proofs: result.last_block_id_proofs,
             },
-            H256(result.expected_data_commitment),
+            result.expected_data_commitment.into(),
         )
     }
 
@@ -528,39 +1354,1422 @@ pub(crate) mod tests {
         circuit.verify(&proof, &input, &output);
     }
 
+    /// Compute the Tendermint (RFC 6962) Merkle root of the leaves, as celestia-core does.
+    fn simple_merkle_root(leaves: &[Vec<u8>]) -> [u8; 32] {
+        if leaves.len() == 1 {
+            return Sha256::digest([&[0u8][..], &leaves[0]].concat()).into();
+        }
+        // Split at the largest power of two less than the number of leaves.
+        let split = leaves.len().next_power_of_two() / 2;
+        let left = simple_merkle_root(&leaves[..split]);
+        let right = simple_merkle_root(&leaves[split..]);
+        Sha256::digest([&[1u8][..], &left, &right].concat()).into()
+    }
+
+    // Compare get_data_commitment over nb_blocks synthetic data hashes with the native RFC 6962 root, for ranges shorter than
+    // MAX_LEAVES whose leaf count isn't a power of two.
+    fn test_get_data_commitment_synthetic_template<const MAX_LEAVES: usize>(nb_blocks: usize) {
+        env_logger::try_init().unwrap_or_default();
+
+        const START_BLOCK: u64 = 10000;
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+        let data_hashes = builder.read::<ArrayVariable<Bytes32Variable, MAX_LEAVES>>();
+        let start_block = builder.constant::<U64Variable>(START_BLOCK);
+        let end_block = builder.constant::<U64Variable>(START_BLOCK + nb_blocks as u64);
+        let data_commitment =
+            builder.get_data_commitment::<MAX_LEAVES>(&data_hashes, start_block, end_block);
+        builder.write(data_commitment);
+
+        let circuit = builder.build();
+
+        // Data hashes after end_block are padding, and must not affect the data commitment.
+        let data_hash_values = (0..MAX_LEAVES)
+            .map(|i| H256::repeat_byte(i as u8 + 1))
+            .collect::<Vec<_>>();
+        let mut input = circuit.input();
+        input.write::<ArrayVariable<Bytes32Variable, MAX_LEAVES>>(data_hash_values.clone());
+        let (proof, mut output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+
+        let encoded_tuples = data_hash_values[..nb_blocks]
+            .iter()
+            .enumerate()
+            .map(|(i, data_hash)| {
+                ethers::abi::encode(&[
+                    Token::Uint(U256::from(START_BLOCK + i as u64)),
+                    Token::FixedBytes(data_hash.as_bytes().to_vec()),
+                ])
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            output.read::<Bytes32Variable>(),
+            H256(simple_merkle_root(&encoded_tuples))
+        );
+    }
+
+    fn test_get_data_commitment_for_window_template<
+        const WINDOW_RANGE: usize,
+        const MAX_LEAVES: usize,
+    >() {
+        env_logger::try_init().unwrap_or_default();
+
+        const START_BLOCK: u64 = 10000;
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+        let data_hashes = builder.read::<ArrayVariable<Bytes32Variable, WINDOW_RANGE>>();
+        let start_block = builder.constant::<U64Variable>(START_BLOCK);
+        let data_commitment = builder
+            .get_data_commitment_for_window::<WINDOW_RANGE, MAX_LEAVES>(&data_hashes, start_block);
+        builder.write(data_commitment);
+
+        let circuit = builder.build();
+
+        let data_hash_values = (0..WINDOW_RANGE)
+            .map(|i| H256::repeat_byte(i as u8 + 1))
+            .collect::<Vec<_>>();
+        let mut input = circuit.input();
+        input.write::<ArrayVariable<Bytes32Variable, WINDOW_RANGE>>(data_hash_values.clone());
+        let (proof, mut output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+
+        let encoded_tuples = data_hash_values
+            .iter()
+            .enumerate()
+            .map(|(i, data_hash)| {
+                ethers::abi::encode(&[
+                    Token::Uint(U256::from(START_BLOCK + i as u64)),
+                    Token::FixedBytes(data_hash.as_bytes().to_vec()),
+                ])
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            output.read::<Bytes32Variable>(),
+            H256(simple_merkle_root(&encoded_tuples))
+        );
+    }
+
+    #[test]
+    fn test_get_data_commitment_for_window_6() {
+        test_get_data_commitment_for_window_template::<6, 8>();
+    }
+
+    #[test]
+    #[should_panic(expected = "MAX_LEAVES must equal WINDOW_RANGE.next_power_of_two()")]
+    fn test_get_data_commitment_for_window_inconsistent_max_leaves() {
+        test_get_data_commitment_for_window_template::<6, 16>();
+    }
+
+    fn test_get_data_commitment_unpadded_template<const WINDOW_RANGE: usize>(
+        data_hash_values: Vec<H256>,
+    ) -> H256 {
+        env_logger::try_init().unwrap_or_default();
+
+        const START_BLOCK: u64 = 10000;
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+        let data_hashes = builder.read::<ArrayVariable<Bytes32Variable, WINDOW_RANGE>>();
+        let start_block = builder.constant::<U64Variable>(START_BLOCK);
+        let data_commitment =
+            builder.get_data_commitment_unpadded::<WINDOW_RANGE>(&data_hashes, start_block);
+        builder.write(data_commitment);
+
+        let circuit = builder.build();
+
+        let mut input = circuit.input();
+        input.write::<ArrayVariable<Bytes32Variable, WINDOW_RANGE>>(data_hash_values.clone());
+        let (proof, mut output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+        let data_commitment = output.read::<Bytes32Variable>();
+
+        let encoded_tuples = data_hash_values
+            .iter()
+            .enumerate()
+            .map(|(i, data_hash)| {
+                ethers::abi::encode(&[
+                    Token::Uint(U256::from(START_BLOCK + i as u64)),
+                    Token::FixedBytes(data_hash.as_bytes().to_vec()),
+                ])
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(data_commitment, H256(simple_merkle_root(&encoded_tuples)));
+        data_commitment
+    }
+
+    fn synthetic_data_hashes(nb_blocks: usize) -> Vec<H256> {
+        (0..nb_blocks)
+            .map(|i| H256::repeat_byte(i as u8 + 1))
+            .collect()
+    }
+
+    #[test]
+    fn test_get_data_commitment_unpadded_mocha_4() {
+        // The data commitment of [10000, 10004) from a Mocha-4 node, over the data hashes of the fixture headers.
+        let data_hash_values = (10000..10004)
+            .map(|height| {
+                let fixture = std::fs::read_to_string(format!(
+                    "circuits/fixtures/mocha-4/{}/header.json",
+                    height
+                ))
+                .unwrap();
+                let response: serde_json::Value = serde_json::from_str(&fixture).unwrap();
+                H256::from(
+                    Hash32::from_rpc_hex(
+                        response["result"]["header"]["data_hash"].as_str().unwrap(),
+                    )
+                    .unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            test_get_data_commitment_unpadded_template::<4>(data_hash_values),
+            H256::from(
+                Hash32::from_rpc_hex(
+                    "5F1B8536FE9C3B95585B4FF913835C5D6E74645F737B12FDDD0B6B4C197C5B95"
+                )
+                .unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn test_get_data_commitment_unpadded_3() {
+        test_get_data_commitment_unpadded_template::<3>(synthetic_data_hashes(3));
+    }
+
+    #[test]
+    fn test_get_data_commitment_unpadded_5() {
+        test_get_data_commitment_unpadded_template::<5>(synthetic_data_hashes(5));
+    }
+
+    #[test]
+    fn test_get_data_commitment_unpadded_6() {
+        test_get_data_commitment_unpadded_template::<6>(synthetic_data_hashes(6));
+    }
+
+    #[test]
+    fn test_get_data_commitment_unpadded_7() {
+        test_get_data_commitment_unpadded_template::<7>(synthetic_data_hashes(7));
+    }
+
+    #[test]
+    fn test_get_data_commitment_with_heights() {
+        env_logger::try_init().unwrap_or_default();
+
+        // Commit to 3 sparse heights in 4 leaves. The last leaf is disabled.
+        const MAX_LEAVES: usize = 4;
+        const NB_LEAVES: usize = 3;
+        let height_values = vec![10000u64, 10007, 10042, 0];
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+        let data_hashes = builder.read::<ArrayVariable<Bytes32Variable, MAX_LEAVES>>();
+        let block_heights = builder.read::<ArrayVariable<U64Variable, MAX_LEAVES>>();
+        let nb_leaves = builder.read::<U64Variable>();
+        let data_commitment = builder.get_data_commitment_with_heights::<MAX_LEAVES>(
+            &data_hashes,
+            &block_heights,
+            nb_leaves,
+        );
+        builder.write(data_commitment);
+
+        let circuit = builder.build();
+
+        let data_hash_values = (0..MAX_LEAVES)
+            .map(|i| H256::repeat_byte(i as u8 + 1))
+            .collect::<Vec<_>>();
+        let mut input = circuit.input();
+        input.write::<ArrayVariable<Bytes32Variable, MAX_LEAVES>>(data_hash_values.clone());
+        input.write::<ArrayVariable<U64Variable, MAX_LEAVES>>(height_values.clone());
+        input.write::<U64Variable>(NB_LEAVES as u64);
+        let (proof, mut output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+
+        let encoded_tuples = (0..NB_LEAVES)
+            .map(|i| {
+                ethers::abi::encode(&[
+                    Token::Uint(U256::from(height_values[i])),
+                    Token::FixedBytes(data_hash_values[i].as_bytes().to_vec()),
+                ])
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            output.read::<Bytes32Variable>(),
+            H256(simple_merkle_root(&encoded_tuples))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "was set twice with different values")]
+    fn test_get_data_commitment_range_exceeds_max_leaves() {
+        env_logger::try_init().unwrap_or_default();
+
+        // A range of 8 blocks doesn't fit in 4 leaves, and must fail instead of committing to the first 4 blocks.
+        const MAX_LEAVES: usize = 4;
+        const START_BLOCK: u64 = 10000;
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+        let data_hashes = builder.read::<ArrayVariable<Bytes32Variable, MAX_LEAVES>>();
+        let start_block = builder.constant::<U64Variable>(START_BLOCK);
+        let end_block = builder.constant::<U64Variable>(START_BLOCK + 8);
+        let data_commitment =
+            builder.get_data_commitment::<MAX_LEAVES>(&data_hashes, start_block, end_block);
+        builder.write(data_commitment);
+
+        let circuit = builder.build();
+
+        let mut input = circuit.input();
+        input.write::<ArrayVariable<Bytes32Variable, MAX_LEAVES>>(
+            (0..MAX_LEAVES)
+                .map(|i| H256::repeat_byte(i as u8 + 1))
+                .collect::<Vec<_>>(),
+        );
+        let (proof, output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+    }
+
+    #[test]
+    fn test_get_data_commitment_synthetic_3() {
+        test_get_data_commitment_synthetic_template::<4>(3);
+    }
+
+    #[test]
+    fn test_get_data_commitment_synthetic_5() {
+        test_get_data_commitment_synthetic_template::<8>(5);
+    }
+
     #[test]
     #[cfg_attr(feature = "ci", ignore)]
-    fn test_prove_header_chain() {
+    fn test_get_data_commitment_synthetic_100() {
+        test_get_data_commitment_synthetic_template::<128>(100);
+    }
+
+    fn test_compute_data_commitment_by_peaks_template<const MAX_LEAVES: usize>(nb_blocks: usize) {
+        env_logger::try_init().unwrap_or_default();
+
+        const START_BLOCK: u64 = 10000;
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+        let data_hashes = builder.read::<ArrayVariable<Bytes32Variable, MAX_LEAVES>>();
+        let nb_leaves = builder.read::<U64Variable>();
+        let start_block = builder.constant::<U64Variable>(START_BLOCK);
+        let data_commitment = compute_data_commitment_by_peaks::<L, D, MAX_LEAVES>(
+            &mut builder,
+            &data_hashes,
+            start_block,
+            nb_leaves,
+        );
+        builder.write(data_commitment);
+
+        let circuit = builder.build();
+
+        let data_hash_values = (0..MAX_LEAVES)
+            .map(|i| H256::repeat_byte(i as u8 + 1))
+            .collect::<Vec<_>>();
+        let mut input = circuit.input();
+        input.write::<ArrayVariable<Bytes32Variable, MAX_LEAVES>>(data_hash_values.clone());
+        input.write::<U64Variable>(nb_blocks as u64);
+        let (proof, mut output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+
+        let encoded_tuples = data_hash_values[..nb_blocks]
+            .iter()
+            .enumerate()
+            .map(|(i, data_hash)| {
+                ethers::abi::encode(&[
+                    Token::Uint(U256::from(START_BLOCK + i as u64)),
+                    Token::FixedBytes(data_hash.as_bytes().to_vec()),
+                ])
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            output.read::<Bytes32Variable>(),
+            H256(simple_merkle_root(&encoded_tuples))
+        );
+    }
+
+    #[test]
+    fn test_compute_data_commitment_by_peaks() {
+        test_compute_data_commitment_by_peaks_template::<8>(1);
+        test_compute_data_commitment_by_peaks_template::<8>(6);
+        test_compute_data_commitment_by_peaks_template::<8>(7);
+        test_compute_data_commitment_by_peaks_template::<8>(8);
+    }
+
+    #[test]
+    #[should_panic(expected = "was set twice with different values")]
+    fn test_compute_data_commitment_by_peaks_no_leaves() {
+        test_compute_data_commitment_by_peaks_template::<8>(0);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    fn test_data_commitment_matches_abi_encoding() {
         env_logger::try_init().unwrap_or_default();
 
         let mut builder = CircuitBuilder::<L, D>::new();
 
         const MAX_LEAVES: usize = 4;
         const START_BLOCK: usize = 10000;
-        let start_block = builder.constant::<U64Variable>(START_BLOCK as u64);
         const END_BLOCK: usize = START_BLOCK + MAX_LEAVES;
+
+        let data_hashes = builder.read::<ArrayVariable<Bytes32Variable, MAX_LEAVES>>();
+        let start_block = builder.constant::<U64Variable>(START_BLOCK as u64);
         let end_block = builder.constant::<U64Variable>(END_BLOCK as u64);
+        let data_commitment =
+            builder.get_data_commitment::<MAX_LEAVES>(&data_hashes, start_block, end_block);
+        builder.write(data_commitment);
 
-        let data_commitment_var = builder.read::<DataCommitmentProofVariable<MAX_LEAVES>>();
+        let circuit = builder.build();
 
-        builder.prove_subchain::<MAX_LEAVES>(
-            &data_commitment_var,
+        let (inputs, rpc_data_commitment) =
+            generate_data_commitment_value_inputs::<MAX_LEAVES>(START_BLOCK, END_BLOCK);
+        let data_hash_values = inputs
+            .data_hash_proofs
+            .iter()
+            .map(|proof| H256::from_slice(&proof.leaf[2..2 + HASH_SIZE]))
+            .collect::<Vec<_>>();
+
+        let mut input = circuit.input();
+        input.write::<ArrayVariable<Bytes32Variable, MAX_LEAVES>>(data_hash_values.clone());
+        let (proof, mut output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+        let circuit_data_commitment = output.read::<Bytes32Variable>();
+
+        // Encode each leaf as abi.encode(DataRootTuple(height, dataRoot)), as the Solidity
+        // DataRootTuple consumer does in verifyAttestation.
+        let encoded_tuples = data_hash_values
+            .iter()
+            .enumerate()
+            .map(|(i, data_hash)| {
+                ethers::abi::encode(&[
+                    Token::Uint(U256::from(START_BLOCK + i)),
+                    Token::FixedBytes(data_hash.as_bytes().to_vec()),
+                ])
+            })
+            .collect::<Vec<_>>();
+        let expected_data_commitment = H256(simple_merkle_root(&encoded_tuples));
+
+        assert_eq!(circuit_data_commitment, expected_data_commitment);
+        assert_eq!(circuit_data_commitment, rpc_data_commitment);
+    }
+
+    #[test]
+    fn test_get_data_commitment_excluding() {
+        env_logger::try_init().unwrap_or_default();
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        const MAX_LEAVES: usize = 4;
+        const NB_EXCLUDED: usize = 2;
+        const START_BLOCK: usize = 10000;
+        const END_BLOCK: usize = START_BLOCK + MAX_LEAVES;
+
+        let data_hashes = builder.read::<ArrayVariable<Bytes32Variable, MAX_LEAVES>>();
+        let excluded_heights = builder.read::<ArrayVariable<U64Variable, NB_EXCLUDED>>();
+        let start_block = builder.constant::<U64Variable>(START_BLOCK as u64);
+        let end_block = builder.constant::<U64Variable>(END_BLOCK as u64);
+        let data_commitment = builder.get_data_commitment_excluding::<MAX_LEAVES, NB_EXCLUDED>(
+            &data_hashes,
             start_block,
             end_block,
-            end_block,
-            data_commitment_var.end_header,
+            &excluded_heights,
         );
+        builder.write(data_commitment);
 
         let circuit = builder.build();
 
+        let data_hash_values = (0..MAX_LEAVES)
+            .map(|i| H256::repeat_byte(i as u8 + 1))
+            .collect::<Vec<_>>();
+
+        // Exclude start_block + 2. The second slot is unused, so it's set to a height outside the range.
         let mut input = circuit.input();
+        input.write::<ArrayVariable<Bytes32Variable, MAX_LEAVES>>(data_hash_values.clone());
+        input.write::<ArrayVariable<U64Variable, NB_EXCLUDED>>(vec![START_BLOCK as u64 + 2, 0]);
+        let (proof, mut output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+        let circuit_data_commitment = output.read::<Bytes32Variable>();
+
+        // Construct the tree manually, with the excluded leaf's data hash replaced by zero.
+        let encoded_tuples = data_hash_values
+            .iter()
+            .enumerate()
+            .map(|(i, data_hash)| {
+                let data_hash = if i == 2 { H256::zero() } else { *data_hash };
+                ethers::abi::encode(&[
+                    Token::Uint(U256::from(START_BLOCK + i)),
+                    Token::FixedBytes(data_hash.as_bytes().to_vec()),
+                ])
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            circuit_data_commitment,
+            H256(simple_merkle_root(&encoded_tuples))
+        );
+    }
 
-        // Generate test cases from Celestia blocks:
-        input.write::<DataCommitmentProofVariable<MAX_LEAVES>>(
-            generate_data_commitment_value_inputs(START_BLOCK, END_BLOCK).0,
+    #[test]
+    fn test_prove_commitment_batch() {
+        env_logger::try_init().unwrap_or_default();
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        const MAX_LEAVES: usize = 2;
+        const NB_WINDOWS: usize = 3;
+
+        let window_data_hashes =
+            builder.read::<ArrayVariable<ArrayVariable<Bytes32Variable, MAX_LEAVES>, NB_WINDOWS>>();
+        let start_blocks = builder.read::<ArrayVariable<U64Variable, NB_WINDOWS>>();
+        let end_blocks = builder.read::<ArrayVariable<U64Variable, NB_WINDOWS>>();
+        let claimed_data_commitments = builder.read::<ArrayVariable<Bytes32Variable, NB_WINDOWS>>();
+        let (all_valid, is_window_valid) = builder.prove_commitment_batch(
+            &window_data_hashes,
+            &start_blocks,
+            &end_blocks,
+            &claimed_data_commitments,
         );
-        let (proof, output) = circuit.prove(&input);
+        builder.write(all_valid);
+        builder.write(is_window_valid);
+
+        let circuit = builder.build();
+
+        // Windows [10000, 10002), [10002, 10004) and [10004, 10005).
+        let start_block_values = vec![10000u64, 10002, 10004];
+        let end_block_values = vec![10002u64, 10004, 10005];
+        let window_data_hash_values = (0..NB_WINDOWS)
+            .map(|i| {
+                (0..MAX_LEAVES)
+                    .map(|j| H256::repeat_byte((i * MAX_LEAVES + j + 1) as u8))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let mut claimed_data_commitment_values = (0..NB_WINDOWS)
+            .map(|i| {
+                let nb_blocks = (end_block_values[i] - start_block_values[i]) as usize;
+                let encoded_tuples = window_data_hash_values[i][..nb_blocks]
+                    .iter()
+                    .enumerate()
+                    .map(|(j, data_hash)| {
+                        ethers::abi::encode(&[
+                            Token::Uint(U256::from(start_block_values[i] + j as u64)),
+                            Token::FixedBytes(data_hash.as_bytes().to_vec()),
+                        ])
+                    })
+                    .collect::<Vec<_>>();
+                H256(simple_merkle_root(&encoded_tuples))
+            })
+            .collect::<Vec<_>>();
+        // Claim a wrong root for the second window.
+        claimed_data_commitment_values[1] = H256::repeat_byte(0xff);
+
+        let mut input = circuit.input();
+        input.write::<ArrayVariable<ArrayVariable<Bytes32Variable, MAX_LEAVES>, NB_WINDOWS>>(
+            window_data_hash_values,
+        );
+        input.write::<ArrayVariable<U64Variable, NB_WINDOWS>>(start_block_values);
+        input.write::<ArrayVariable<U64Variable, NB_WINDOWS>>(end_block_values);
+        input.write::<ArrayVariable<Bytes32Variable, NB_WINDOWS>>(claimed_data_commitment_values);
+        let (proof, mut output) = circuit.prove(&input);
         circuit.verify(&proof, &input, &output);
+
+        assert!(!output.read::<BoolVariable>());
+        assert_eq!(
+            output.read::<ArrayVariable<BoolVariable, NB_WINDOWS>>(),
+            vec![true, false, true]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    fn test_data_commitment_proof_deterministic() {
+        env_logger::try_init().unwrap_or_default();
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        const MAX_LEAVES: usize = 4;
+        const START_BLOCK: usize = 10000;
+        const END_BLOCK: usize = START_BLOCK + MAX_LEAVES;
+
+        let data_commitment_var = builder.read::<DataCommitmentProofVariable<MAX_LEAVES>>();
+
+        let start_block = builder.constant::<U64Variable>(START_BLOCK as u64);
+        let end_block = builder.constant::<U64Variable>(END_BLOCK as u64);
+
+        let data_hashes = ArrayVariable::<Bytes32Variable, MAX_LEAVES>::from(
+            data_commitment_var
+                .data_hash_proofs
+                .data
+                .iter()
+                .map(|proof| Bytes32Variable::from(&proof.leaf[2..2 + HASH_SIZE]))
+                .collect::<Vec<_>>(),
+        );
+
+        let root_hash_target =
+            builder.get_data_commitment::<MAX_LEAVES>(&data_hashes, start_block, end_block);
+        builder.write(root_hash_target);
+
+        let circuit = builder.build();
+
+        // Inputs are written positionally in the order they are read by the circuit, so the
+        // witness is identical across runs.
+        let mut input = circuit.input();
+        input.write::<DataCommitmentProofVariable<MAX_LEAVES>>(
+            generate_data_commitment_value_inputs(START_BLOCK, END_BLOCK).0,
+        );
+
+        // Proving is deterministic for a fixed circuit and witness (zero-knowledge blinding is
+        // disabled), so both proofs over the same window must serialize to identical bytes.
+        let (first_proof, first_output) = circuit.prove(&input);
+        circuit.verify(&first_proof, &input, &first_output);
+        let (second_proof, second_output) = circuit.prove(&input);
+        circuit.verify(&second_proof, &input, &second_output);
+
+        assert_eq!(first_proof.to_bytes(), second_proof.to_bytes());
+    }
+
+    fn test_prove_header_chain_template(start_height: usize) {
+        env_logger::try_init().unwrap_or_default();
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        const MAX_LEAVES: usize = 4;
+        let start_block = builder.constant::<U64Variable>(start_height as u64);
+        let end_height = start_height + MAX_LEAVES;
+        let end_block = builder.constant::<U64Variable>(end_height as u64);
+
+        let data_commitment_var = builder.read::<DataCommitmentProofVariable<MAX_LEAVES>>();
+
+        builder.prove_subchain::<MAX_LEAVES>(
+            &data_commitment_var,
+            start_block,
+            end_block,
+            end_block,
+            data_commitment_var.end_header,
+        );
+
+        let circuit = builder.build();
+
+        let mut input = circuit.input();
+
+        // Generate test cases from Celestia blocks:
+        input.write::<DataCommitmentProofVariable<MAX_LEAVES>>(
+            generate_data_commitment_value_inputs(start_height, end_height).0,
+        );
+        let (proof, output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    fn test_prove_header_chain() {
+        test_prove_header_chain_template(10000);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    fn test_prove_header_chain_genesis() {
+        // Block 1 has a null last_block_id, which must not be read when the range starts at 1.
+        test_prove_header_chain_template(1);
+    }
+
+    /// Build a synthetic chain of num_headers linked headers, starting from the Mocha-4 header at 10000. Each later header
+    /// copies its parent, links to it through last_block_id and, if data_hash_is_prev_header is set, also uses the parent's
+    /// header hash as its data hash.
+    pub(crate) fn generate_synthetic_header_chain(
+        num_headers: usize,
+        data_hash_is_prev_header: bool,
+    ) -> Vec<Header> {
+        let fixture = std::fs::read_to_string("circuits/fixtures/mocha-4/10000/header.json")
+            .expect("failed to read header fixture");
+        let response: serde_json::Value = serde_json::from_str(&fixture).unwrap();
+        let mut headers: Vec<Header> =
+            vec![serde_json::from_value(response["result"]["header"].clone()).unwrap()];
+
+        for _ in 1..num_headers {
+            let prev = headers.last().unwrap();
+            let mut header = prev.clone();
+            header.height = prev.height.increment();
+            header.last_block_id = Some(BlockId {
+                hash: prev.hash(),
+                part_set_header: prev.last_block_id.unwrap().part_set_header,
+            });
+            if data_hash_is_prev_header {
+                header.data_hash = Some(prev.hash());
+            }
+            headers.push(header);
+        }
+        headers
+    }
+
+    /// Relink a synthetic chain after its headers were modified, by setting each header's last_block_id hash to the new hash
+    /// of its parent.
+    fn relink_header_chain(headers: &mut [Header]) {
+        for i in 1..headers.len() {
+            let prev_hash = headers[i - 1].hash();
+            headers[i].last_block_id.as_mut().unwrap().hash = prev_hash;
+        }
+    }
+
+    // Follow the data hash and header hash of the Mocha-4 header at 10000 from the RPC's hex encoding, through the native
+    // header, the witness and the circuit's EVM output, and check no stage reverses their byte order.
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    fn test_hash_byte_order_round_trip() {
+        env_logger::try_init().unwrap_or_default();
+
+        let header = generate_synthetic_header_chain(1, false).remove(0);
+        let read_fixture = |path: &str| -> serde_json::Value {
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+        };
+        let rpc_data_hex = read_fixture("circuits/fixtures/mocha-4/10000/header.json")["result"]
+            ["header"]["data_hash"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        // The header hash of 10000 is the last_block_id hash of 10001.
+        let rpc_header_hex = read_fixture("circuits/fixtures/mocha-4/10001/header.json")["result"]
+            ["header"]["last_block_id"]["hash"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let rpc_data_hash = Hash32::from_rpc_hex(&rpc_data_hex).unwrap();
+        let rpc_header_hash = Hash32::from_rpc_hex(&rpc_header_hex).unwrap();
+
+        // RPC hex -> Hash32 -> RPC hex.
+        assert_eq!(rpc_data_hash.to_rpc_hex(), rpc_data_hex.to_uppercase());
+        assert_eq!(rpc_header_hash.to_rpc_hex(), rpc_header_hex.to_uppercase());
+
+        // RPC hex -> native header.
+        assert_eq!(
+            Hash32::try_from(header.data_hash.unwrap()).unwrap(),
+            rpc_data_hash
+        );
+        assert_eq!(Hash32::try_from(header.hash()).unwrap(), rpc_header_hash);
+
+        // Native header -> witness.
+        let data_hash_proof = InputDataFetcher::default()
+            .get_inclusion_proof::<PROTOBUF_HASH_SIZE_BYTES, F>(
+                &header,
+                DATA_HASH_INDEX as u64,
+                header.data_hash.unwrap().encode_vec(),
+            );
+        assert_eq!(data_hash_proof.leaf[2..2 + HASH_SIZE], rpc_data_hash.0);
+
+        // Witness -> extracted Bytes32Variable -> EVM output.
+        let mut builder = CircuitBuilder::<L, D>::new();
+        let data_hash_proof_var = builder
+            .read::<MerkleInclusionProofVariable<HEADER_PROOF_DEPTH, PROTOBUF_HASH_SIZE_BYTES>>();
+        let data_hash_path =
+            builder.constant::<ArrayVariable<BoolVariable, 4>>(vec![false, true, true, false]);
+        let header_hash = builder
+            .get_root_from_merkle_proof::<HEADER_PROOF_DEPTH, PROTOBUF_HASH_SIZE_BYTES>(
+                &data_hash_proof_var,
+                &data_hash_path,
+            );
+        let data_hash = Bytes32Variable::from(&data_hash_proof_var.leaf[2..2 + HASH_SIZE]);
+        builder.evm_write(header_hash);
+        builder.evm_write(data_hash);
+        let circuit = builder.build();
+
+        let mut input = circuit.input();
+        input.write::<MerkleInclusionProofVariable<HEADER_PROOF_DEPTH, PROTOBUF_HASH_SIZE_BYTES>>(
+            header_inclusion_proof(data_hash_proof.proof, data_hash_proof.leaf).unwrap(),
+        );
+        let (proof, mut output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+
+        assert_eq!(
+            output.evm_read::<Bytes32Variable>(),
+            H256::from(rpc_header_hash)
+        );
+        assert_eq!(
+            output.evm_read::<Bytes32Variable>(),
+            H256::from(rpc_data_hash)
+        );
+    }
+
+    fn test_prove_synthetic_header_chain_template(
+        headers: Vec<Header>,
+        swap_data_hash_proof: bool,
+    ) {
+        env_logger::try_init().unwrap_or_default();
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        const MAX_LEAVES: usize = 4;
+        const START_BLOCK: u64 = 10000;
+        let start_block = builder.constant::<U64Variable>(START_BLOCK);
+        let end_block = builder.constant::<U64Variable>(START_BLOCK + MAX_LEAVES as u64);
+
+        let data_commitment_var = builder.read::<DataCommitmentProofVariable<MAX_LEAVES>>();
+        let subchain = builder.prove_subchain::<MAX_LEAVES>(
+            &data_commitment_var,
+            start_block,
+            end_block,
+            end_block,
+            data_commitment_var.end_header,
+        );
+        builder.write(subchain.data_merkle_root);
+
+        let circuit = builder.build();
+
+        let fetcher = InputDataFetcher::default();
+        let mut inputs = fetcher
+            .get_data_commitment_proof_from_headers::<MAX_LEAVES, F>(&headers)
+            .unwrap();
+        if swap_data_hash_proof {
+            // Prove the data hash of block 10001 with the last_block_id's position in the header instead of the data hash's.
+            let swapped_proof = fetcher.get_inclusion_proof::<PROTOBUF_HASH_SIZE_BYTES, F>(
+                &headers[1],
+                LAST_BLOCK_ID_INDEX as u64,
+                headers[1].data_hash.unwrap().encode_vec(),
+            );
+            inputs.data_hash_proofs[1].proof = swapped_proof.proof;
+        }
+
+        let mut input = circuit.input();
+        input.write::<DataCommitmentProofVariable<MAX_LEAVES>>(inputs);
+        let (proof, mut output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+
+        let encoded_tuples = headers[..MAX_LEAVES]
+            .iter()
+            .map(|header| {
+                ethers::abi::encode(&[
+                    Token::Uint(U256::from(header.height.value())),
+                    Token::FixedBytes(header.data_hash.unwrap().as_bytes().to_vec()),
+                ])
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            output.read::<Bytes32Variable>(),
+            H256(simple_merkle_root(&encoded_tuples))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    fn test_prove_header_chain_data_hash_is_prev_header() {
+        // In every header after the first, the data hash and the last_block_id hash are the same 32 bytes.
+        test_prove_synthetic_header_chain_template(generate_synthetic_header_chain(5, true), false);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    #[should_panic(expected = "was set twice with different values")]
+    fn test_prove_header_chain_data_hash_is_prev_header_swapped() {
+        // A proof at the last_block_id's index can't stand in for the data hash proof, even though the hashes are equal.
+        test_prove_synthetic_header_chain_template(generate_synthetic_header_chain(5, true), true);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    #[should_panic(expected = "was set twice with different values")]
+    fn test_prove_subchain_mismatched_data_hash() {
+        env_logger::try_init().unwrap_or_default();
+
+        // prove_subchain commits to the data hashes in the leaves of data_hash_proofs, so a data hash that isn't the one in
+        // its block's header fails the check against the header hash.
+        const MAX_LEAVES: usize = 4;
+        const START_BLOCK: u64 = 10000;
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+        let start_block = builder.constant::<U64Variable>(START_BLOCK);
+        let end_block = builder.constant::<U64Variable>(START_BLOCK + MAX_LEAVES as u64);
+        let data_commitment_var = builder.read::<DataCommitmentProofVariable<MAX_LEAVES>>();
+        let subchain = builder.prove_subchain::<MAX_LEAVES>(
+            &data_commitment_var,
+            start_block,
+            end_block,
+            end_block,
+            data_commitment_var.end_header,
+        );
+        builder.write(subchain.data_merkle_root);
+
+        let circuit = builder.build();
+
+        let headers = generate_synthetic_header_chain(MAX_LEAVES + 1, false);
+        let mut inputs = InputDataFetcher::default()
+            .get_data_commitment_proof_from_headers::<MAX_LEAVES, F>(&headers)
+            .unwrap();
+        inputs.data_hash_proofs[1].leaf[2..2 + HASH_SIZE].copy_from_slice(&[0xab; HASH_SIZE]);
+
+        let mut input = circuit.input();
+        input.write::<DataCommitmentProofVariable<MAX_LEAVES>>(inputs);
+        let (proof, output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    fn test_prove_header_chain_across_app_upgrade() {
+        // An app version upgrade at 10002 changes the version leaf of the header, which the data commitment circuits only
+        // hash as an aunt. The data hash and last_block_id leaves are encoded the same on both sides of the upgrade.
+        let mut headers = generate_synthetic_header_chain(5, false);
+        for header in headers[2..].iter_mut() {
+            header.version.app += 1;
+        }
+        relink_header_chain(&mut headers);
+        test_prove_synthetic_header_chain_template(headers, false);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    fn test_prove_subchain_variable_num_blocks() {
+        env_logger::try_init().unwrap_or_default();
+
+        // The number of blocks is a witness, so one circuit with 16 leaves proves a 3-block range. The links and leaves after
+        // the range are disabled.
+        const MAX_LEAVES: usize = 16;
+        const START_BLOCK: u64 = 10000;
+        const NUM_BLOCKS: usize = 3;
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+        let num_blocks = builder.read::<U64Variable>();
+        let data_commitment_var = builder.read::<DataCommitmentProofVariable<MAX_LEAVES>>();
+        let start_block = builder.constant::<U64Variable>(START_BLOCK);
+        let end_block = builder.add(start_block, num_blocks);
+        builder.assert_valid_block_range(start_block, end_block, MAX_LEAVES);
+        let subchain = builder.prove_subchain::<MAX_LEAVES>(
+            &data_commitment_var,
+            start_block,
+            end_block,
+            end_block,
+            data_commitment_var.end_header,
+        );
+        builder.write(subchain.end_header);
+        builder.write(subchain.data_merkle_root);
+
+        let circuit = builder.build();
+
+        let headers = generate_synthetic_header_chain(NUM_BLOCKS + 1, false);
+        let inputs = InputDataFetcher::default()
+            .get_data_commitment_proof_from_headers::<MAX_LEAVES, F>(&headers)
+            .unwrap();
+
+        let mut input = circuit.input();
+        input.write::<U64Variable>(NUM_BLOCKS as u64);
+        input.write::<DataCommitmentProofVariable<MAX_LEAVES>>(inputs);
+        let (proof, mut output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+
+        assert_eq!(
+            output.read::<Bytes32Variable>(),
+            H256::from_slice(headers[NUM_BLOCKS].hash().as_bytes())
+        );
+        let encoded_tuples = headers[..NUM_BLOCKS]
+            .iter()
+            .map(|header| {
+                ethers::abi::encode(&[
+                    Token::Uint(U256::from(header.height.value())),
+                    Token::FixedBytes(header.data_hash.unwrap().as_bytes().to_vec()),
+                ])
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            output.read::<Bytes32Variable>(),
+            H256(simple_merkle_root(&encoded_tuples))
+        );
+    }
+
+    // Prove the synthetic chain [10000, 10000 + BACK + FWD] from the anchor at 10000 + BACK. If forge_forward_chain is set, the
+    // forward headers are replaced by headers that are linked to the anchor but don't end at the trusted high header.
+    fn test_prove_header_chain_bidirectional_template<const BACK: usize, const FWD: usize>(
+        forge_forward_chain: bool,
+    ) {
+        env_logger::try_init().unwrap_or_default();
+
+        let headers = generate_synthetic_header_chain(BACK + FWD + 1, false);
+        let anchor = &headers[BACK];
+        let high = &headers[BACK + FWD];
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        let anchor_block = builder.read::<U64Variable>();
+        let anchor_header_hash = builder.read::<Bytes32Variable>();
+        let high_header_hash = builder.read::<Bytes32Variable>();
+        let back_proof = builder.read::<DataCommitmentProofVariable<BACK>>();
+        let fwd_proof = builder.read::<DataCommitmentProofVariable<FWD>>();
+        let chain = builder.prove_header_chain_bidirectional::<BACK, FWD>(
+            anchor_block,
+            anchor_header_hash,
+            high_header_hash,
+            &back_proof,
+            &fwd_proof,
+        );
+        builder.write(chain.start_block);
+        builder.write(chain.start_header);
+        builder.write(chain.end_block);
+        builder.write(chain.end_header);
+        builder.write(chain.data_merkle_root);
+
+        let circuit = builder.build();
+
+        let mut forward_headers = headers[BACK..].to_vec();
+        if forge_forward_chain {
+            for header in forward_headers[1..].iter_mut() {
+                header.version.app += 1;
+            }
+            relink_header_chain(&mut forward_headers);
+        }
+
+        let fetcher = InputDataFetcher::default();
+        let back_inputs = fetcher
+            .get_data_commitment_proof_from_headers::<BACK, F>(&headers[..BACK + 1])
+            .unwrap();
+        let fwd_inputs = fetcher
+            .get_data_commitment_proof_from_headers::<FWD, F>(&forward_headers)
+            .unwrap();
+
+        let mut input = circuit.input();
+        input.write::<U64Variable>(anchor.height.value());
+        input.write::<Bytes32Variable>(H256::from_slice(anchor.hash().as_bytes()));
+        input.write::<Bytes32Variable>(H256::from_slice(high.hash().as_bytes()));
+        input.write::<DataCommitmentProofVariable<BACK>>(back_inputs);
+        input.write::<DataCommitmentProofVariable<FWD>>(fwd_inputs);
+        let (proof, mut output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+
+        let low = &headers[0];
+        assert_eq!(output.read::<U64Variable>(), low.height.value());
+        assert_eq!(
+            output.read::<Bytes32Variable>(),
+            H256::from_slice(low.hash().as_bytes())
+        );
+        assert_eq!(output.read::<U64Variable>(), high.height.value());
+        assert_eq!(
+            output.read::<Bytes32Variable>(),
+            H256::from_slice(high.hash().as_bytes())
+        );
+
+        // The data commitment covers [low, high), with the backward segment before the forward segment.
+        let encoded_tuples = headers[..BACK + FWD]
+            .iter()
+            .map(|header| {
+                ethers::abi::encode(&[
+                    Token::Uint(U256::from(header.height.value())),
+                    Token::FixedBytes(header.data_hash.unwrap().as_bytes().to_vec()),
+                ])
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            output.read::<Bytes32Variable>(),
+            H256(simple_merkle_root(&encoded_tuples))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    fn test_prove_header_chain_bidirectional() {
+        test_prove_header_chain_bidirectional_template::<2, 2>(false);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    fn test_prove_header_chain_bidirectional_fwd_not_power_of_two() {
+        // The forward segment's root over 3 leaves must be the right subtree of the RFC 6962 tree over 7 leaves.
+        test_prove_header_chain_bidirectional_template::<4, 3>(false);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    #[should_panic(expected = "was set twice with different values")]
+    fn test_prove_header_chain_bidirectional_forged_forward_chain() {
+        // Headers linked forwards from the anchor can't stand in for the chain ending at the trusted high header.
+        test_prove_header_chain_bidirectional_template::<2, 2>(true);
+    }
+
+    fn build_prove_subchain_circuit<const MAX_LEAVES: usize>() -> CircuitBuild<L, D> {
+        let mut builder = CircuitBuilder::<L, D>::new();
+        let start_block = builder.read::<U64Variable>();
+        let data_commitment_var = builder.read::<DataCommitmentProofVariable<MAX_LEAVES>>();
+        let max_leaves = builder.constant::<U64Variable>(MAX_LEAVES as u64);
+        let end_block = builder.add(start_block, max_leaves);
+        let subchain = builder.prove_subchain::<MAX_LEAVES>(
+            &data_commitment_var,
+            start_block,
+            end_block,
+            end_block,
+            data_commitment_var.end_header,
+        );
+        builder.write(subchain.data_merkle_root);
+        builder.build()
+    }
+
+    // The shape of the header chain circuit must not depend on its inputs: every selection in prove_subchain's loop is made
+    // with in-circuit selectors, and no native input sizes a buffer. Two builds have the same digest, and one build proves
+    // chains with different heights (and so different height leaf lengths) and different data hashes.
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    fn test_prove_subchain_circuit_is_input_independent() {
+        env_logger::try_init().unwrap_or_default();
+
+        const MAX_LEAVES: usize = 4;
+        let circuit = build_prove_subchain_circuit::<MAX_LEAVES>();
+        let rebuilt_circuit = build_prove_subchain_circuit::<MAX_LEAVES>();
+        assert_eq!(
+            circuit.data.verifier_only.circuit_digest,
+            rebuilt_circuit.data.verifier_only.circuit_digest
+        );
+
+        // The varint encoding of 10000 is 2 bytes, and the encoding of 3000000 is 4 bytes.
+        let mut shifted_headers = generate_synthetic_header_chain(MAX_LEAVES + 1, true);
+        for (i, header) in shifted_headers.iter_mut().enumerate() {
+            header.height = (3_000_000 + i as u64).try_into().unwrap();
+        }
+        relink_header_chain(&mut shifted_headers);
+
+        let fetcher = InputDataFetcher::default();
+        for headers in [
+            generate_synthetic_header_chain(MAX_LEAVES + 1, false),
+            shifted_headers,
+        ] {
+            let inputs = fetcher
+                .get_data_commitment_proof_from_headers::<MAX_LEAVES, F>(&headers)
+                .unwrap();
+            let mut input = circuit.input();
+            input.write::<U64Variable>(headers[0].height.value());
+            input.write::<DataCommitmentProofVariable<MAX_LEAVES>>(inputs);
+            let (proof, mut output) = circuit.prove(&input);
+            rebuilt_circuit.verify(&proof, &input, &output);
+
+            let encoded_tuples = headers[..MAX_LEAVES]
+                .iter()
+                .map(|header| {
+                    ethers::abi::encode(&[
+                        Token::Uint(U256::from(header.height.value())),
+                        Token::FixedBytes(header.data_hash.unwrap().as_bytes().to_vec()),
+                    ])
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                output.read::<Bytes32Variable>(),
+                H256(simple_merkle_root(&encoded_tuples))
+            );
+        }
+    }
+
+    fn test_is_valid_last_block_id_template(parts_total: u8) -> bool {
+        env_logger::try_init().unwrap_or_default();
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        let last_block_id = builder.read::<BytesVariable<PROTOBUF_BLOCK_ID_SIZE_BYTES>>();
+        let is_valid = builder.is_valid_last_block_id(&last_block_id);
+        builder.write(is_valid);
+
+        let circuit = builder.build();
+
+        let mut encoded_block_id = vec![0x0a, 0x20];
+        encoded_block_id.extend([1u8; HASH_SIZE]);
+        encoded_block_id.extend([0x12, 0x24, 0x08, parts_total, 0x12, 0x20]);
+        encoded_block_id.extend([2u8; HASH_SIZE]);
+
+        let mut input = circuit.input();
+        input.write::<BytesVariable<PROTOBUF_BLOCK_ID_SIZE_BYTES>>(
+            encoded_block_id.try_into().unwrap(),
+        );
+        let (proof, mut output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+
+        output.read::<BoolVariable>()
+    }
+
+    #[test]
+    fn test_is_valid_last_block_id() {
+        assert!(test_is_valid_last_block_id_template(1));
+    }
+
+    #[test]
+    fn test_is_valid_last_block_id_nil() {
+        // A BlockID with parts_total = 0 is the nil BlockID and must be rejected.
+        assert!(!test_is_valid_last_block_id_template(0));
+    }
+
+    fn test_valid_block_range_template(start_block: u64, end_block: u64, max_num_blocks: usize) {
+        env_logger::try_init().unwrap_or_default();
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        let start_block_var = builder.read::<U64Variable>();
+        let end_block_var = builder.read::<U64Variable>();
+        builder.assert_valid_block_range(start_block_var, end_block_var, max_num_blocks);
+
+        let circuit = builder.build();
+
+        let mut input = circuit.input();
+        input.write::<U64Variable>(start_block);
+        input.write::<U64Variable>(end_block);
+        let (proof, output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+    }
+
+    #[test]
+    fn test_valid_block_range() {
+        test_valid_block_range_template(10000, 10004, 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "was set twice with different values")]
+    fn test_valid_block_range_wrapped() {
+        // start_block + max_num_blocks wraps around to 2, so end_block would pass the upper bound
+        // check if wraparound was not constrained.
+        test_valid_block_range_template(u64::MAX - 1, 1, 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "max_num_blocks must be < MAX_NUM_BLOCKS_BOUND")]
+    fn test_valid_block_range_bound() {
+        test_valid_block_range_template(10000, 10004, MAX_NUM_BLOCKS_BOUND);
+    }
+
+    #[test]
+    fn test_assert_distinct_data_hashes_ignores_padding() {
+        env_logger::try_init().unwrap_or_default();
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        // The last data hash duplicates the first, but it is past end_block.
+        let data_hashes = builder.constant::<ArrayVariable<Bytes32Variable, 4>>(vec![
+            H256::repeat_byte(1),
+            H256::repeat_byte(2),
+            H256::repeat_byte(3),
+            H256::repeat_byte(1),
+        ]);
+        let start_block = builder.constant::<U64Variable>(10000);
+        let end_block = builder.constant::<U64Variable>(10003);
+        builder.assert_distinct_data_hashes::<4>(&data_hashes, start_block, end_block);
+
+        let circuit = builder.build();
+        let input = circuit.input();
+        let (proof, output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+    }
+
+    #[test]
+    #[should_panic(expected = "was set twice with different values")]
+    fn test_assert_distinct_data_hashes_duplicate() {
+        env_logger::try_init().unwrap_or_default();
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        // The third data hash duplicates the first, and both are inside the window.
+        let data_hashes = builder.constant::<ArrayVariable<Bytes32Variable, 4>>(vec![
+            H256::repeat_byte(1),
+            H256::repeat_byte(2),
+            H256::repeat_byte(1),
+            H256::repeat_byte(3),
+        ]);
+        let start_block = builder.constant::<U64Variable>(10000);
+        let end_block = builder.constant::<U64Variable>(10004);
+        builder.assert_distinct_data_hashes::<4>(&data_hashes, start_block, end_block);
+
+        let circuit = builder.build();
+        let input = circuit.input();
+        let (proof, output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+    }
+
+    fn test_assert_min_nonempty_template(min_nonempty: u32) {
+        env_logger::try_init().unwrap_or_default();
+
+        const MAX_LEAVES: usize = 4;
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        let window_data_hashes = builder.read::<ArrayVariable<Bytes32Variable, MAX_LEAVES>>();
+        let min_nonempty_var = builder.read::<U32Variable>();
+        builder.assert_min_nonempty::<MAX_LEAVES>(&window_data_hashes, min_nonempty_var);
+
+        let circuit = builder.build();
+
+        // 2 of the 4 blocks in the window are non-empty.
+        let mut input = circuit.input();
+        input.write::<ArrayVariable<Bytes32Variable, MAX_LEAVES>>(vec![
+            H256::repeat_byte(1),
+            H256::zero(),
+            H256::repeat_byte(2),
+            H256::zero(),
+        ]);
+        input.write::<U32Variable>(min_nonempty);
+        let (proof, output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+    }
+
+    #[test]
+    fn test_assert_min_nonempty_above_threshold() {
+        test_assert_min_nonempty_template(1);
+    }
+
+    #[test]
+    fn test_assert_min_nonempty_at_threshold() {
+        test_assert_min_nonempty_template(2);
+    }
+
+    #[test]
+    #[should_panic(expected = "was set twice with different values")]
+    fn test_assert_min_nonempty_below_threshold() {
+        test_assert_min_nonempty_template(3);
+    }
+
+    fn test_chains_onto_recent_commitment_template(new_start_block: u64) -> H256 {
+        env_logger::try_init().unwrap_or_default();
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        const K: usize = 3;
+        let recent_end_blocks =
+            builder.constant::<ArrayVariable<U64Variable, K>>(vec![10000, 10004, 10008]);
+        let recent_data_commitments = builder.constant::<ArrayVariable<Bytes32Variable, K>>(vec![
+            H256::repeat_byte(1),
+            H256::repeat_byte(2),
+            H256::repeat_byte(3),
+        ]);
+        let new_start_block = builder.constant::<U64Variable>(new_start_block);
+
+        let matched_data_commitment = builder.assert_chains_onto_recent_commitment::<K>(
+            &recent_end_blocks,
+            &recent_data_commitments,
+            new_start_block,
+        );
+        builder.write(matched_data_commitment);
+
+        let circuit = builder.build();
+        let input = circuit.input();
+        let (proof, mut output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+
+        output.read::<Bytes32Variable>()
+    }
+
+    #[test]
+    fn test_chains_onto_recent_commitment() {
+        let matched_data_commitment = test_chains_onto_recent_commitment_template(10004);
+        assert_eq!(matched_data_commitment, H256::repeat_byte(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "was set twice with different values")]
+    fn test_chains_onto_recent_commitment_no_match() {
+        test_chains_onto_recent_commitment_template(10005);
+    }
+
+    fn test_height_not_in_range_template(height: u64) {
+        env_logger::try_init().unwrap_or_default();
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        let height_var = builder.read::<U64Variable>();
+        let start_block = builder.constant::<U64Variable>(10000);
+        let end_block = builder.constant::<U64Variable>(10004);
+        builder.prove_height_not_in_range(height_var, start_block, end_block);
+
+        let circuit = builder.build();
+
+        let mut input = circuit.input();
+        input.write::<U64Variable>(height);
+        let (proof, output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+    }
+
+    #[test]
+    fn test_height_not_in_range_before_start() {
+        test_height_not_in_range_template(9999);
+    }
+
+    #[test]
+    #[should_panic(expected = "was set twice with different values")]
+    fn test_height_not_in_range_start() {
+        test_height_not_in_range_template(10000);
+    }
+
+    #[test]
+    #[should_panic(expected = "was set twice with different values")]
+    fn test_height_not_in_range_last_block() {
+        test_height_not_in_range_template(10003);
+    }
+
+    #[test]
+    fn test_height_not_in_range_end() {
+        // end_block is exclusive, so it is not in the committed range.
+        test_height_not_in_range_template(10004);
+    }
+
+    fn test_prove_receipt_template(height: u64, end_block: u64) -> bool {
+        env_logger::try_init().unwrap_or_default();
+
+        const PROOF_DEPTH: usize = 2;
+        const START_BLOCK: u64 = 10000;
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        let height_var = builder.read::<U64Variable>();
+        let data_hash = builder.read::<Bytes32Variable>();
+        let data_commitment = builder.read::<Bytes32Variable>();
+        let proof = builder.read::<ArrayVariable<Bytes32Variable, PROOF_DEPTH>>();
+        let path = builder.read::<ArrayVariable<BoolVariable, PROOF_DEPTH>>();
+        let start_block = builder.constant::<U64Variable>(START_BLOCK);
+        let end_block = builder.constant::<U64Variable>(end_block);
+        let verified = builder.prove_receipt::<PROOF_DEPTH>(
+            height_var,
+            data_hash,
+            start_block,
+            end_block,
+            data_commitment,
+            &proof,
+            &path,
+        );
+        builder.write(verified);
+
+        let circuit = builder.build();
+
+        // A tree over the 4 blocks [START_BLOCK, START_BLOCK + 4) with synthetic data hashes.
+        let data_hashes = (0..4u8).map(|i| H256([i + 1; 32])).collect::<Vec<_>>();
+        let encoded_tuples = data_hashes
+            .iter()
+            .enumerate()
+            .map(|(i, data_hash)| {
+                ethers::abi::encode(&[
+                    Token::Uint(U256::from(START_BLOCK + i as u64)),
+                    Token::FixedBytes(data_hash.as_bytes().to_vec()),
+                ])
+            })
+            .collect::<Vec<_>>();
+        let data_commitment_value = H256(simple_merkle_root(&encoded_tuples));
+
+        // The aunts of the receipt's leaf are its sibling leaf and the root of the other half of the tree.
+        let leaf_index = (height - START_BLOCK) as usize;
+        let sibling_index = leaf_index ^ 1;
+        let other_half = if leaf_index < 2 { 2..4 } else { 0..2 };
+        let proof_value = vec![
+            H256(simple_merkle_root(
+                &encoded_tuples[sibling_index..sibling_index + 1],
+            )),
+            H256(simple_merkle_root(&encoded_tuples[other_half])),
+        ];
+        let path_value = vec![leaf_index & 1 == 1, leaf_index & 2 == 2];
+
+        let mut input = circuit.input();
+        input.write::<U64Variable>(height);
+        input.write::<Bytes32Variable>(data_hashes[leaf_index]);
+        input.write::<Bytes32Variable>(data_commitment_value);
+        input.write::<ArrayVariable<Bytes32Variable, PROOF_DEPTH>>(proof_value);
+        input.write::<ArrayVariable<BoolVariable, PROOF_DEPTH>>(path_value);
+        let (proof, mut output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+        output.read::<BoolVariable>()
+    }
+
+    #[test]
+    fn test_prove_receipt() {
+        assert!(test_prove_receipt_template(10001, 10004));
+        assert!(test_prove_receipt_template(10002, 10004));
+    }
+
+    #[test]
+    fn test_prove_receipt_out_of_range() {
+        // The receipt is a leaf of the tree, but the claimed range ends before its height.
+        assert!(!test_prove_receipt_template(10003, 10003));
     }
 
     #[test]
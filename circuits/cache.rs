@@ -0,0 +1,519 @@
+use std::any::type_name;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use std::{fs, io};
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use plonky2x::backend::circuit::{Circuit, CircuitBuild};
+use plonky2x::prelude::plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
+use plonky2x::prelude::{CircuitBuilder, GateRegistry, HintRegistry, PlonkParameters};
+use sha2::{Digest, Sha256};
+
+/// Cache entries are prefixed with the crate version, so a new version never loads a circuit built
+/// by an older one.
+const CACHE_VERSION_PREFIX: &str = concat!("v", env!("CARGO_PKG_VERSION"), "-");
+
+/// A hash of the circuit's type (including its const generics, e.g. NB_MAP_JOBS and BATCH_SIZE), the
+/// plonk parameters and the crate version, which identifies a build of circuit C.
+pub fn circuit_parameters_hash<C: Circuit, L: PlonkParameters<D>, const D: usize>() -> [u8; 32] {
+    let key = format!(
+        "{}|{}|{}|{}",
+        type_name::<C>(),
+        type_name::<L>(),
+        D,
+        env!("CARGO_PKG_VERSION")
+    );
+    Sha256::digest(key.as_bytes()).into()
+}
+
+/// The path of the cached build of circuit C with parameters L and D in cache_dir, keyed by
+/// circuit_parameters_hash.
+pub fn circuit_cache_path<C: Circuit, L: PlonkParameters<D>, const D: usize>(
+    cache_dir: &Path,
+) -> PathBuf {
+    cache_dir.join(format!(
+        "{}{}.circuit",
+        CACHE_VERSION_PREFIX,
+        alloy_primitives::hex::encode(circuit_parameters_hash::<C, L, D>())
+    ))
+}
+
+/// Build circuit C, or load it from cache_dir if it was built before with the same parameters and
+/// crate version. The prover and verifier data are serialized with C's gates and generators.
+/// Entries written by other crate versions are removed from cache_dir.
+pub fn build_cached<C: Circuit, L: PlonkParameters<D>, const D: usize>(
+    cache_dir: &Path,
+) -> Result<CircuitBuild<L, D>>
+where
+    <<L as PlonkParameters<D>>::Config as GenericConfig<D>>::Hasher: AlgebraicHasher<L::Field>,
+{
+    let mut hint_registry = HintRegistry::<L, D>::new();
+    let mut gate_registry = GateRegistry::<L, D>::new();
+    C::register_generators::<L, D>(&mut hint_registry);
+    C::register_gates::<L, D>(&mut gate_registry);
+
+    fs::create_dir_all(cache_dir)?;
+    prune_stale_entries(cache_dir)?;
+
+    let path = circuit_cache_path::<C, L, D>(cache_dir);
+    if path.exists() {
+        let bytes = fs::read(&path)?;
+        match CircuitBuild::<L, D>::deserialize(&bytes, &gate_registry, &hint_registry) {
+            Ok(circuit) => {
+                info!("Loaded {} from {}", type_name::<C>(), path.display());
+                return Ok(circuit);
+            }
+            // A corrupt entry (e.g. from an interrupted write) is rebuilt below.
+            Err(e) => warn!("Failed to load {}: {:?}", path.display(), e),
+        }
+    }
+
+    info!("Building {}", type_name::<C>());
+    let mut builder = CircuitBuilder::<L, D>::new();
+    C::define::<L, D>(&mut builder);
+    let circuit = builder.build();
+
+    let bytes = circuit
+        .serialize(&gate_registry, &hint_registry)
+        .map_err(|e| anyhow!("failed to serialize {}: {:?}", type_name::<C>(), e))?;
+    // Write to a temporary file first, so a concurrent or interrupted build never leaves a partial
+    // entry at path.
+    let tmp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&tmp_path, bytes)?;
+    fs::rename(&tmp_path, &path)?;
+    info!("Cached {} at {}", type_name::<C>(), path.display());
+
+    Ok(circuit)
+}
+
+/// Remove the cache entries written by other crate versions.
+fn prune_stale_entries(cache_dir: &Path) -> Result<()> {
+    for entry in fs::read_dir(cache_dir)? {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if file_name.ends_with(".circuit") && !file_name.starts_with(CACHE_VERSION_PREFIX) {
+            info!("Removing stale circuit cache entry {}", path.display());
+            match fs::remove_file(&path) {
+                Ok(()) => {}
+                // A concurrent build may have removed the entry already.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The labels of a circuit's metrics in a CircuitRegistry, which identify it without parsing its type name.
+pub trait CircuitLabels {
+    /// The kind of the circuit, e.g. "data_commitment" or "next_header".
+    const KIND: &'static str;
+    /// The maximum number of blocks the circuit proves over.
+    const WINDOW_SIZE: usize;
+}
+
+/// Size and build statistics of a circuit in a CircuitRegistry, recorded once when it is built or loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitBuildStats {
+    /// The circuit's type name, which includes its const generics (e.g. the window size).
+    pub circuit: String,
+    /// The circuit's CircuitLabels::KIND.
+    pub kind: &'static str,
+    /// The circuit's CircuitLabels::WINDOW_SIZE.
+    pub window_size: usize,
+    /// The number of gate types in the circuit.
+    pub num_gate_types: usize,
+    /// The number of rows (the degree) of the circuit.
+    pub num_rows: usize,
+    pub num_public_inputs: usize,
+    /// The size of the circuit's cache entry.
+    pub artifact_size_bytes: u64,
+    /// The time taken to build the circuit, or to load it from the cache directory.
+    pub build_duration: Duration,
+}
+
+struct RegisteredCircuit<L: PlonkParameters<D>, const D: usize> {
+    circuit: Arc<CircuitBuild<L, D>>,
+    stats: CircuitBuildStats,
+}
+
+type RegistryEntry<L, const D: usize> = Arc<OnceLock<Result<RegisteredCircuit<L, D>, String>>>;
+
+/// Built circuits shared between prover threads, keyed by circuit_parameters_hash (so e.g. each window size of a data
+/// commitment circuit is its own entry). Every entry has its own OnceLock: each circuit is built at most once, builds of
+/// different circuits proceed concurrently, and the map's lock is never held during a build, so lookups of built circuits
+/// never wait on unrelated builds. A failed build is stored, and later lookups return its error instead of rebuilding.
+pub struct CircuitRegistry<L: PlonkParameters<D>, const D: usize> {
+    cache_dir: PathBuf,
+    entries: RwLock<HashMap<[u8; 32], RegistryEntry<L, D>>>,
+}
+
+impl<L: PlonkParameters<D>, const D: usize> CircuitRegistry<L, D>
+where
+    <<L as PlonkParameters<D>>::Config as GenericConfig<D>>::Hasher: AlgebraicHasher<L::Field>,
+{
+    /// Create an empty registry, whose circuits are built with build_cached in cache_dir.
+    pub fn new(cache_dir: PathBuf) -> Self {
+        Self {
+            cache_dir,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Get circuit C, building it (or loading it from the cache directory) on first use. Concurrent callers for the same
+    /// circuit wait for the one build in progress.
+    pub fn get<C: Circuit + CircuitLabels>(&self) -> Result<Arc<CircuitBuild<L, D>>> {
+        let key = circuit_parameters_hash::<C, L, D>();
+        let existing = self.entries.read().unwrap().get(&key).cloned();
+        let entry = match existing {
+            Some(entry) => entry,
+            None => self
+                .entries
+                .write()
+                .unwrap()
+                .entry(key)
+                .or_default()
+                .clone(),
+        };
+
+        match entry.get_or_init(|| self.build::<C>()) {
+            Ok(registered) => Ok(registered.circuit.clone()),
+            Err(e) => Err(anyhow!("failed to build {}: {}", type_name::<C>(), e)),
+        }
+    }
+
+    fn build<C: Circuit + CircuitLabels>(&self) -> Result<RegisteredCircuit<L, D>, String> {
+        let start = Instant::now();
+        let circuit = build_cached::<C, L, D>(&self.cache_dir).map_err(|e| format!("{:?}", e))?;
+        let build_duration = start.elapsed();
+
+        let artifact_size_bytes = fs::metadata(circuit_cache_path::<C, L, D>(&self.cache_dir))
+            .map(|metadata| metadata.len())
+            .unwrap_or_default();
+        let stats = CircuitBuildStats {
+            circuit: type_name::<C>().to_string(),
+            kind: C::KIND,
+            window_size: C::WINDOW_SIZE,
+            num_gate_types: circuit.data.common.gates.len(),
+            num_rows: circuit.data.common.degree(),
+            num_public_inputs: circuit.data.common.num_public_inputs,
+            artifact_size_bytes,
+            build_duration,
+        };
+        info!("Built {:?}", stats);
+
+        Ok(RegisteredCircuit {
+            circuit: Arc::new(circuit),
+            stats,
+        })
+    }
+
+    /// The statistics of every circuit built or loaded so far, sorted by circuit. Circuits still being built, and failed
+    /// builds, are skipped.
+    pub fn stats(&self) -> Vec<CircuitBuildStats> {
+        let mut stats = self
+            .entries
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(|entry| entry.get()?.as_ref().ok())
+            .map(|registered| registered.stats.clone())
+            .collect::<Vec<_>>();
+        stats.sort_by(|a, b| a.circuit.cmp(&b.circuit));
+        stats
+    }
+
+    /// Render stats() as gauges in the Prometheus text exposition format, labeled by circuit, kind and window_size.
+    pub fn prometheus_metrics(&self) -> String {
+        let stats = self.stats();
+        let gauges: [(&str, &str, fn(&CircuitBuildStats) -> String); 5] = [
+            (
+                "blobstreamx_circuit_gate_types",
+                "Number of gate types in the circuit.",
+                |stats| stats.num_gate_types.to_string(),
+            ),
+            (
+                "blobstreamx_circuit_rows",
+                "Number of rows of the circuit.",
+                |stats| stats.num_rows.to_string(),
+            ),
+            (
+                "blobstreamx_circuit_public_inputs",
+                "Number of public inputs of the circuit.",
+                |stats| stats.num_public_inputs.to_string(),
+            ),
+            (
+                "blobstreamx_circuit_artifact_size_bytes",
+                "Size of the circuit's cache entry in bytes.",
+                |stats| stats.artifact_size_bytes.to_string(),
+            ),
+            (
+                "blobstreamx_circuit_build_duration_seconds",
+                "Time taken to build or load the circuit in seconds.",
+                |stats| stats.build_duration.as_secs_f64().to_string(),
+            ),
+        ];
+
+        let mut metrics = String::new();
+        for (name, help, value) in gauges {
+            metrics.push_str(&format!(
+                "# HELP {} {}\n# TYPE {} gauge\n",
+                name, help, name
+            ));
+            for circuit_stats in &stats {
+                let label = circuit_stats
+                    .circuit
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"");
+                metrics.push_str(&format!(
+                    "{}{{circuit=\"{}\",kind=\"{}\",window_size=\"{}\"}} {}\n",
+                    name,
+                    label,
+                    circuit_stats.kind,
+                    circuit_stats.window_size,
+                    value(circuit_stats)
+                ));
+            }
+        }
+        metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Barrier;
+    use std::{env, thread};
+
+    use plonky2x::prelude::{DefaultParameters, U64Variable};
+
+    use super::*;
+
+    type L = DefaultParameters;
+    const D: usize = 2;
+
+    #[derive(Debug, Clone)]
+    struct AddCircuit;
+
+    impl Circuit for AddCircuit {
+        fn define<L: PlonkParameters<D>, const D: usize>(builder: &mut CircuitBuilder<L, D>) {
+            let a = builder.read::<U64Variable>();
+            let b = builder.read::<U64Variable>();
+            let sum = builder.add(a, b);
+            builder.write(sum);
+        }
+    }
+
+    impl CircuitLabels for AddCircuit {
+        const KIND: &'static str = "add";
+        const WINDOW_SIZE: usize = 2;
+    }
+
+    #[derive(Debug, Clone)]
+    struct SumCircuit<const N: usize>;
+
+    impl<const N: usize> CircuitLabels for SumCircuit<N> {
+        const KIND: &'static str = "sum";
+        const WINDOW_SIZE: usize = N;
+    }
+
+    impl<const N: usize> Circuit for SumCircuit<N> {
+        fn define<L: PlonkParameters<D>, const D: usize>(builder: &mut CircuitBuilder<L, D>) {
+            let mut sum = builder.constant::<U64Variable>(0);
+            for _ in 0..N {
+                let value = builder.read::<U64Variable>();
+                sum = builder.add(sum, value);
+            }
+            builder.write(sum);
+        }
+    }
+
+    fn prove_sum<const N: usize>(circuit: &CircuitBuild<L, D>) -> u64 {
+        let mut input = circuit.input();
+        for i in 0..N {
+            input.write::<U64Variable>(i as u64 + 1);
+        }
+        let (proof, mut output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+        output.read::<U64Variable>()
+    }
+
+    #[test]
+    fn test_build_cached() {
+        env_logger::try_init().unwrap_or_default();
+
+        let cache_dir = env::temp_dir().join(format!("blobstreamx-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&cache_dir);
+
+        // An entry from another crate version should be removed.
+        fs::create_dir_all(&cache_dir).unwrap();
+        let stale_path = cache_dir.join("v0.0.0-stale.circuit");
+        fs::write(&stale_path, b"stale").unwrap();
+
+        let path = circuit_cache_path::<AddCircuit, L, D>(&cache_dir);
+        let built = build_cached::<AddCircuit, L, D>(&cache_dir).unwrap();
+        assert!(path.exists());
+        assert!(!stale_path.exists());
+
+        // The second build loads from the cache, and its proofs verify with the cached verifier data.
+        let cached_at = fs::metadata(&path).unwrap().modified().unwrap();
+        let loaded = build_cached::<AddCircuit, L, D>(&cache_dir).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), cached_at);
+
+        let mut input = loaded.input();
+        input.write::<U64Variable>(2);
+        input.write::<U64Variable>(3);
+        let (proof, mut output) = loaded.prove(&input);
+        loaded.verify(&proof, &input, &output);
+        built.verify(&proof, &input, &output);
+        assert_eq!(output.read::<U64Variable>(), 5);
+
+        fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[test]
+    fn test_circuit_registry_concurrent() {
+        env_logger::try_init().unwrap_or_default();
+
+        let cache_dir =
+            env::temp_dir().join(format!("blobstreamx-registry-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&cache_dir);
+        let registry = CircuitRegistry::<L, D>::new(cache_dir.clone());
+
+        let sum_1 = registry.get::<SumCircuit<1>>().unwrap();
+        let sum_2 = registry.get::<SumCircuit<2>>().unwrap();
+
+        // Two workers prove with different window sizes while two more request a window size that isn't built yet.
+        let barrier = Barrier::new(4);
+        let (sums, builds) = thread::scope(|s| {
+            let provers = [
+                s.spawn(|| {
+                    barrier.wait();
+                    prove_sum::<1>(&registry.get::<SumCircuit<1>>().unwrap())
+                }),
+                s.spawn(|| {
+                    barrier.wait();
+                    prove_sum::<2>(&registry.get::<SumCircuit<2>>().unwrap())
+                }),
+            ];
+            let builders = [
+                s.spawn(|| {
+                    barrier.wait();
+                    registry.get::<SumCircuit<4>>().unwrap()
+                }),
+                s.spawn(|| {
+                    barrier.wait();
+                    registry.get::<SumCircuit<4>>().unwrap()
+                }),
+            ];
+            (
+                provers.map(|prover| prover.join().unwrap()),
+                builders.map(|builder| builder.join().unwrap()),
+            )
+        });
+        assert_eq!(sums, [1, 3]);
+
+        // The concurrent requests share a single build, and built circuits are reused.
+        assert!(Arc::ptr_eq(&builds[0], &builds[1]));
+        assert!(Arc::ptr_eq(
+            &registry.get::<SumCircuit<1>>().unwrap(),
+            &sum_1
+        ));
+        assert!(Arc::ptr_eq(
+            &registry.get::<SumCircuit<2>>().unwrap(),
+            &sum_2
+        ));
+        assert_eq!(prove_sum::<4>(&builds[0]), 10);
+
+        fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[test]
+    fn test_circuit_registry_caches_build_failure() {
+        env_logger::try_init().unwrap_or_default();
+
+        // The cache directory can't be created while a file is at its path.
+        let cache_dir =
+            env::temp_dir().join(format!("blobstreamx-registry-err-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&cache_dir);
+        fs::write(&cache_dir, b"not a directory").unwrap();
+        let registry = CircuitRegistry::<L, D>::new(cache_dir.clone());
+        assert!(registry.get::<AddCircuit>().is_err());
+
+        // The failure is returned again without retrying the build, even though it would now succeed.
+        fs::remove_file(&cache_dir).unwrap();
+        assert!(registry.get::<AddCircuit>().is_err());
+        assert!(!cache_dir.exists());
+    }
+
+    #[test]
+    fn test_circuit_registry_prometheus_metrics() {
+        env_logger::try_init().unwrap_or_default();
+
+        let cache_dir = env::temp_dir().join(format!("blobstreamx-metrics-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&cache_dir);
+        let registry = CircuitRegistry::<L, D>::new(cache_dir.clone());
+        assert!(registry
+            .prometheus_metrics()
+            .lines()
+            .all(|line| line.starts_with('#')));
+
+        registry.get::<SumCircuit<2>>().unwrap();
+        registry.get::<AddCircuit>().unwrap();
+
+        let stats = registry.stats();
+        assert_eq!(stats.len(), 2);
+        for circuit_stats in &stats {
+            assert!(circuit_stats.num_gate_types > 0);
+            assert!(circuit_stats.num_rows.is_power_of_two());
+            assert!(circuit_stats.num_public_inputs > 0);
+            assert!(circuit_stats.artifact_size_bytes > 0);
+        }
+
+        // Each gauge has a sample per circuit, labeled with the circuit's type name, kind and window size.
+        let metrics = registry.prometheus_metrics();
+        let sum_rows = format!(
+            "blobstreamx_circuit_rows{{circuit=\"{}\",kind=\"sum\",window_size=\"2\"}} ",
+            type_name::<SumCircuit<2>>()
+        );
+        assert!(metrics.contains(&format!(
+            "blobstreamx_circuit_rows{{circuit=\"{}\",kind=\"add\",window_size=\"2\"}} ",
+            type_name::<AddCircuit>()
+        )));
+        let sample = metrics
+            .lines()
+            .find(|line| line.starts_with(&sum_rows))
+            .unwrap();
+        let rows: usize = sample[sum_rows.len()..].parse().unwrap();
+        assert_eq!(
+            rows,
+            stats
+                .iter()
+                .find(|s| s.circuit.contains("SumCircuit<2>"))
+                .unwrap()
+                .num_rows
+        );
+        for name in [
+            "blobstreamx_circuit_gate_types",
+            "blobstreamx_circuit_rows",
+            "blobstreamx_circuit_public_inputs",
+            "blobstreamx_circuit_artifact_size_bytes",
+            "blobstreamx_circuit_build_duration_seconds",
+        ] {
+            assert!(metrics.contains(&format!("# TYPE {} gauge\n", name)));
+            assert_eq!(
+                metrics
+                    .lines()
+                    .filter(|line| line.starts_with(&format!("{}{{", name)))
+                    .count(),
+                2
+            );
+        }
+
+        fs::remove_dir_all(&cache_dir).unwrap();
+    }
+}
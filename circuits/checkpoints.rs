@@ -0,0 +1,120 @@
+use anyhow::{anyhow, ensure, Result};
+use tendermint::block::Header;
+use tendermintx::config::{CELESTIA_CHAIN_ID_BYTES, MOCHA_4_CHAIN_ID_BYTES};
+
+use crate::utils::Hash32;
+
+/// A header compiled into the binary, which a fresh deployment can use as its first trusted header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub name: &'static str,
+    pub chain_id: &'static str,
+    pub height: u64,
+    /// The header hash, hex-encoded as in celestia-core RPC responses.
+    pub header_hash: &'static str,
+}
+
+/// The chain ids of the networks BlobstreamX is configured for (see config.rs).
+pub const KNOWN_CHAIN_IDS: &[&[u8]] = &[CELESTIA_CHAIN_ID_BYTES, MOCHA_4_CHAIN_ID_BYTES];
+
+/// Known checkpoints. Adding one is a code change, checked by test_checkpoints_are_consistent.
+/// There is no celestia (mainnet) checkpoint yet: its header hash must be taken from a mainnet node
+/// and checked against its header fixture, as mocha-4-10000 is by test_verify_checkpoint_header.
+pub const CHECKPOINTS: &[Checkpoint] = &[Checkpoint {
+    name: "mocha-4-10000",
+    chain_id: "mocha-4",
+    height: 10000,
+    header_hash: "A0123D5E4B8B8888A61F931EE2252D83568B97C223E0ECA9795B29B8BD8CBA2D",
+}];
+
+/// Find the checkpoint named name.
+pub fn find_checkpoint(name: &str) -> Result<&'static Checkpoint> {
+    CHECKPOINTS
+        .iter()
+        .find(|checkpoint| checkpoint.name == name)
+        .ok_or_else(|| {
+            anyhow!(
+                "unknown checkpoint {}, expected one of {:?}",
+                name,
+                CHECKPOINTS.iter().map(|c| c.name).collect::<Vec<_>>()
+            )
+        })
+}
+
+impl Checkpoint {
+    /// The decoded header hash.
+    pub fn header_hash(&self) -> Result<[u8; 32]> {
+        Ok(Hash32::from_rpc_hex(self.header_hash)?.0)
+    }
+
+    /// Check the checkpoint is internally consistent: its chain id is known, its height is positive and its hash parses.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            KNOWN_CHAIN_IDS.contains(&self.chain_id.as_bytes()),
+            "checkpoint {} has unknown chain id {}",
+            self.name,
+            self.chain_id
+        );
+        ensure!(self.height > 0, "checkpoint {} has height 0", self.name);
+        self.header_hash()?;
+        Ok(())
+    }
+
+    /// Check header, e.g. fetched from the configured RPC, is the checkpoint's header.
+    pub fn verify_header(&self, header: &Header) -> Result<()> {
+        ensure!(
+            header.chain_id.as_str() == self.chain_id,
+            "checkpoint {} is for chain {}, but the header is from chain {}",
+            self.name,
+            self.chain_id,
+            header.chain_id
+        );
+        ensure!(
+            header.height.value() == self.height,
+            "checkpoint {} is at height {}, but the header is at height {}",
+            self.name,
+            self.height,
+            header.height.value()
+        );
+        ensure!(
+            header.hash().as_bytes() == self.header_hash()?,
+            "checkpoint {} has header hash {}, but the header's hash is {}",
+            self.name,
+            self.header_hash,
+            header.hash()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_checkpoints_are_consistent() {
+        let mut names = HashSet::new();
+        for checkpoint in CHECKPOINTS {
+            checkpoint.validate().unwrap();
+            assert!(names.insert(checkpoint.name), "duplicate checkpoint name");
+        }
+    }
+
+    #[test]
+    fn test_verify_checkpoint_header() {
+        let fixture =
+            std::fs::read_to_string("circuits/fixtures/mocha-4/10000/header.json").unwrap();
+        let response: serde_json::Value = serde_json::from_str(&fixture).unwrap();
+        let mut header: Header =
+            serde_json::from_value(response["result"]["header"].clone()).unwrap();
+
+        let checkpoint = find_checkpoint("mocha-4-10000").unwrap();
+        checkpoint.verify_header(&header).unwrap();
+
+        header.version.app += 1;
+        assert!(checkpoint.verify_header(&header).is_err());
+        assert!(find_checkpoint("mocha-3-10000").is_err());
+    }
+}
@@ -6,6 +6,11 @@ pub const PROTOBUF_HASH_SIZE_BYTES: usize = HASH_SIZE + 2;
 /// The number of bits in a protobuf-encoded tendermint block ID.
 pub const PROTOBUF_BLOCK_ID_SIZE_BYTES: usize = 72;
 
+// Offsets of the part_set_header's total tag and value in a protobuf-encoded block ID:
+// [0x0a, 0x20, hash (32 bytes), 0x12, 0x24, 0x08, total (1 byte), 0x12, 0x20, parts hash (32 bytes)].
+pub const BLOCK_ID_PARTS_TOTAL_TAG_INDEX: usize = 36;
+pub const BLOCK_ID_PARTS_TOTAL_INDEX: usize = 37;
+
 // Depth of the proofs against the header.
 pub const HEADER_PROOF_DEPTH: usize = 4;
 
@@ -14,6 +19,10 @@ pub const HEADER_PROOF_DEPTH: usize = 4;
 pub const VARINT_BYTES_LENGTH_MAX: usize = 9;
 pub const PROTOBUF_VARINT_SIZE_BYTES: usize = VARINT_BYTES_LENGTH_MAX + 1;
 
+// Upper bound on the number of blocks in a data commitment circuit, so block range arithmetic stays
+// far from u64 limits.
+pub const MAX_NUM_BLOCKS_BOUND: usize = 1 << 20;
+
 // The number of bytes in an encoded data root tuple.
 pub const ENC_DATA_ROOT_TUPLE_SIZE_BYTES: usize = 64;
 
@@ -21,3 +30,6 @@ pub const ENC_DATA_ROOT_TUPLE_SIZE_BYTES: usize = 64;
 pub const BLOCK_HEIGHT_INDEX: usize = 2;
 pub const LAST_BLOCK_ID_INDEX: usize = 4;
 pub const DATA_HASH_INDEX: usize = 6;
+
+// The number of bytes in a Tendermint validator address (truncated SHA256 of the pubkey).
+pub const VALIDATOR_ADDRESS_SIZE_BYTES: usize = 20;
@@ -3,12 +3,15 @@ use ethers::types::H256;
 use plonky2x::backend::circuit::Circuit;
 use plonky2x::frontend::hint::asynchronous::hint::AsyncHint;
 use plonky2x::frontend::mapreduce::generator::MapReduceGenerator;
+use plonky2x::frontend::merkle::tree::MerkleInclusionProofVariable;
 use plonky2x::frontend::uint::uint64::U64Variable;
 use plonky2x::prelude::{Bytes32Variable, CircuitBuilder, PlonkParameters, ValueStream};
 use serde::{Deserialize, Serialize};
 use tendermintx::input::InputDataFetcher;
 
 use crate::builder::{DataCommitmentBuilder, DataCommitmentSharedCtx};
+use crate::cache::CircuitLabels;
+use crate::consts::*;
 use crate::input::DataCommitmentInputFetcher;
 use crate::vars::*;
 
@@ -31,11 +34,12 @@ impl<const MAX_LEAVES: usize, L: PlonkParameters<D>, const D: usize> AsyncHint<L
 
         let result = data_fetcher
             .get_data_commitment_inputs::<MAX_LEAVES, L::Field>(start_block, end_block)
-            .await;
+            .await
+            .expect("invalid data commitment inputs");
 
         let data_comm_proof = DataCommitmentProofValueType {
-            start_header: H256(result.start_header_hash),
-            end_header: H256(result.end_header_hash),
+            start_header: result.start_header_hash.into(),
+            end_header: result.end_header_hash.into(),
             data_hash_proofs: result.data_hash_proofs,
             last_block_id_proofs: result.last_block_id_proofs,
         };
@@ -44,6 +48,32 @@ impl<const MAX_LEAVES: usize, L: PlonkParameters<D>, const D: usize> AsyncHint<L
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataHashProofOffchainInputs {}
+
+#[async_trait]
+impl<L: PlonkParameters<D>, const D: usize> AsyncHint<L, D> for DataHashProofOffchainInputs {
+    async fn hint(
+        &self,
+        input_stream: &mut ValueStream<L, D>,
+        output_stream: &mut ValueStream<L, D>,
+    ) {
+        let block_number = input_stream.read_value::<U64Variable>();
+
+        let data_fetcher = InputDataFetcher::default();
+
+        let data_hash_proof = data_fetcher
+            .get_data_hash_proof::<L::Field>(block_number)
+            .await;
+
+        // Write the data hash proof of the block.
+        output_stream.write_value::<MerkleInclusionProofVariable<
+            HEADER_PROOF_DEPTH,
+            PROTOBUF_HASH_SIZE_BYTES,
+        >>(data_hash_proof);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DataCommitmentCircuit<const NB_MAP_JOBS: usize, const BATCH_SIZE: usize> {
     _config: usize,
@@ -97,14 +127,155 @@ impl<const NB_MAP_JOBS: usize, const BATCH_SIZE: usize> Circuit
     }
 }
 
+impl<const NB_MAP_JOBS: usize, const BATCH_SIZE: usize> CircuitLabels
+    for DataCommitmentCircuit<NB_MAP_JOBS, BATCH_SIZE>
+{
+    const KIND: &'static str = "data_commitment";
+    const WINDOW_SIZE: usize = NB_MAP_JOBS * BATCH_SIZE;
+}
+
+/// Data commitment circuit that computes the commitment with two independent gadget paths and
+/// asserts they agree (see prove_data_commitment_redundant). MAX_LEAVES must equal
+/// NB_MAP_JOBS * BATCH_SIZE. The input and output are the same as DataCommitmentCircuit.
+/// Only built with the redundant-data-commitment feature.
+#[cfg(feature = "redundant-data-commitment")]
+#[derive(Debug, Clone)]
+pub struct DataCommitmentRedundantCircuit<
+    const NB_MAP_JOBS: usize,
+    const BATCH_SIZE: usize,
+    const MAX_LEAVES: usize,
+> {
+    _config: usize,
+}
+
+#[cfg(feature = "redundant-data-commitment")]
+impl<const NB_MAP_JOBS: usize, const BATCH_SIZE: usize, const MAX_LEAVES: usize> Circuit
+    for DataCommitmentRedundantCircuit<NB_MAP_JOBS, BATCH_SIZE, MAX_LEAVES>
+{
+    fn define<L: PlonkParameters<D>, const D: usize>(builder: &mut CircuitBuilder<L, D>) where <<L as plonky2x::prelude::PlonkParameters<D>>::Config as plonky2x::prelude::plonky2::plonk::config::GenericConfig<D>>::Hasher: plonky2x::prelude::plonky2::plonk::config::AlgebraicHasher<<L as plonky2x::prelude::PlonkParameters<D>>::Field>{
+        let start_block_number = builder.evm_read::<U64Variable>();
+        let start_header_hash = builder.evm_read::<Bytes32Variable>();
+        let end_block_number = builder.evm_read::<U64Variable>();
+        let end_header_hash = builder.evm_read::<Bytes32Variable>();
+
+        let data_commitment = builder
+            .prove_data_commitment_redundant::<Self, NB_MAP_JOBS, BATCH_SIZE, MAX_LEAVES>(
+                start_block_number,
+                start_header_hash,
+                end_block_number,
+                end_header_hash,
+            );
+
+        builder.evm_write(data_commitment);
+    }
+
+    fn register_generators<L: PlonkParameters<D>, const D: usize>(
+        generator_registry: &mut plonky2x::prelude::HintRegistry<L, D>,
+    ) where
+    <<L as PlonkParameters<D>>::Config as plonky2x::prelude::plonky2::plonk::config::GenericConfig<D>>::Hasher:
+    plonky2x::prelude::plonky2::plonk::config::AlgebraicHasher<L::Field>,
+    {
+        generator_registry.register_async_hint::<DataCommitmentOffchainInputs<BATCH_SIZE>>();
+        generator_registry.register_async_hint::<DataCommitmentOffchainInputs<MAX_LEAVES>>();
+
+        let mr_id = MapReduceGenerator::<
+            L,
+            DataCommitmentSharedCtx,
+            U64Variable,
+            MapReduceSubchainVariable,
+            Self,
+            BATCH_SIZE,
+            D,
+        >::id();
+        generator_registry.register_simple::<MapReduceGenerator<
+            L,
+            DataCommitmentSharedCtx,
+            U64Variable,
+            MapReduceSubchainVariable,
+            Self,
+            BATCH_SIZE,
+            D,
+        >>(mr_id);
+    }
+}
+
+#[cfg(feature = "redundant-data-commitment")]
+impl<const NB_MAP_JOBS: usize, const BATCH_SIZE: usize, const MAX_LEAVES: usize> CircuitLabels
+    for DataCommitmentRedundantCircuit<NB_MAP_JOBS, BATCH_SIZE, MAX_LEAVES>
+{
+    const KIND: &'static str = "data_commitment_redundant";
+    const WINDOW_SIZE: usize = MAX_LEAVES;
+}
+
+/// The maximum window for which DataCommitmentWithDataHashesCircuit outputs each data hash.
+pub const DATA_HASH_OUTPUTS_MAX_LEAVES: usize = 8;
+
+/// Data commitment circuit for small windows, which also outputs the data hash of each block.
+///
+/// The output is evm-encoded in the following order:
+///     data_commitment (bytes32)
+///     for i in 0..MAX_LEAVES: start_block + i (uint64), data_hash_i (bytes32)
+/// The data hashes of blocks at or after end_block are zero.
+#[derive(Debug, Clone)]
+pub struct DataCommitmentWithDataHashesCircuit<const MAX_LEAVES: usize> {
+    _config: usize,
+}
+
+impl<const MAX_LEAVES: usize> Circuit for DataCommitmentWithDataHashesCircuit<MAX_LEAVES> {
+    fn define<L: PlonkParameters<D>, const D: usize>(builder: &mut CircuitBuilder<L, D>) {
+        assert!(
+            MAX_LEAVES <= DATA_HASH_OUTPUTS_MAX_LEAVES,
+            "MAX_LEAVES must be <= DATA_HASH_OUTPUTS_MAX_LEAVES"
+        );
+
+        let start_block_number = builder.evm_read::<U64Variable>();
+        let start_header_hash = builder.evm_read::<Bytes32Variable>();
+        let end_block_number = builder.evm_read::<U64Variable>();
+        let end_header_hash = builder.evm_read::<Bytes32Variable>();
+
+        let (data_commitment, data_hashes) = builder
+            .prove_data_commitment_with_data_hashes::<MAX_LEAVES>(
+                start_block_number,
+                start_header_hash,
+                end_block_number,
+                end_header_hash,
+            );
+
+        builder.evm_write(data_commitment);
+        for i in 0..MAX_LEAVES {
+            let curr_idx = builder.constant::<U64Variable>(i as u64);
+            let block_height = builder.add(start_block_number, curr_idx);
+            builder.evm_write(block_height);
+            builder.evm_write(data_hashes[i]);
+        }
+    }
+
+    fn register_generators<L: PlonkParameters<D>, const D: usize>(
+        generator_registry: &mut plonky2x::prelude::HintRegistry<L, D>,
+    ) where
+        <<L as PlonkParameters<D>>::Config as plonky2x::prelude::plonky2::plonk::config::GenericConfig<D>>::Hasher:
+            plonky2x::prelude::plonky2::plonk::config::AlgebraicHasher<L::Field>,
+    {
+        generator_registry.register_async_hint::<DataCommitmentOffchainInputs<MAX_LEAVES>>();
+    }
+}
+
+impl<const MAX_LEAVES: usize> CircuitLabels for DataCommitmentWithDataHashesCircuit<MAX_LEAVES> {
+    const KIND: &'static str = "data_commitment_with_data_hashes";
+    const WINDOW_SIZE: usize = MAX_LEAVES;
+}
+
 #[cfg(test)]
 mod tests {
     use std::env;
 
+    use plonky2x::backend::circuit::{PublicInput, PublicOutput};
     use plonky2x::prelude::{DefaultBuilder, GateRegistry, HintRegistry};
     use subtle_encoding::hex;
 
     use super::*;
+    use crate::builder::prove_data_commitment_redundant_with;
+    use crate::public_values::ProofOutputs;
 
     #[test]
     #[cfg_attr(feature = "ci", ignore)]
@@ -134,7 +305,7 @@ mod tests {
         start_header_hash: [u8; 32],
         end_block: usize,
         end_header_hash: [u8; 32],
-    ) {
+    ) -> H256 {
         env::set_var("RUST_LOG", "debug");
         env_logger::try_init().unwrap_or_default();
 
@@ -164,6 +335,307 @@ mod tests {
         circuit.verify(&proof, &input, &output);
         let data_commitment = output.evm_read::<Bytes32Variable>();
         println!("data_commitment {:?}", data_commitment);
+        data_commitment
+    }
+
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    fn test_data_commitment_matches_rpc() {
+        env_logger::try_init().unwrap_or_default();
+        dotenv::dotenv().ok();
+        // The reference data commitment is fetched from celestia-core, so skip if no RPC is set.
+        if env::var("TENDERMINT_RPC_URL").map_or(true, |url| url.is_empty()) {
+            log::warn!("TENDERMINT_RPC_URL is not set, skipping");
+            return;
+        }
+
+        const NB_MAP_JOBS: usize = 2;
+        const BATCH_SIZE: usize = 2;
+
+        let start_block = 10000u64;
+        let end_block = 10004u64;
+
+        let mut data_fetcher = InputDataFetcher::default();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let (start_header_hash, end_header_hash, expected_data_commitment) = rt.block_on(async {
+            let start_signed_header = data_fetcher
+                .get_signed_header_from_number(start_block)
+                .await;
+            let end_signed_header = data_fetcher.get_signed_header_from_number(end_block).await;
+            let expected_data_commitment = data_fetcher
+                .get_data_commitment(start_block, end_block)
+                .await;
+            (
+                start_signed_header.header.hash(),
+                end_signed_header.header.hash(),
+                expected_data_commitment,
+            )
+        });
+
+        let data_commitment = test_data_commitment_template::<NB_MAP_JOBS, BATCH_SIZE>(
+            start_block as usize,
+            start_header_hash.as_bytes().try_into().unwrap(),
+            end_block as usize,
+            end_header_hash.as_bytes().try_into().unwrap(),
+        );
+
+        // The circuit's data commitment should match celestia-core's data_commitment RPC.
+        assert_eq!(data_commitment, H256::from(expected_data_commitment));
+    }
+
+    #[test]
+    #[cfg(feature = "redundant-data-commitment")]
+    #[cfg_attr(feature = "ci", ignore)]
+    fn test_data_commitment_redundant() {
+        env::set_var("RUST_LOG", "debug");
+        env_logger::try_init().unwrap_or_default();
+        dotenv::dotenv().ok();
+
+        const NB_MAP_JOBS: usize = 2;
+        const BATCH_SIZE: usize = 2;
+        const MAX_LEAVES: usize = NB_MAP_JOBS * BATCH_SIZE;
+
+        let start_block = 10000u64;
+        let end_block = 10004u64;
+
+        let mut data_fetcher = InputDataFetcher::default();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let (signed_headers, expected_data_commitment) = rt.block_on(async {
+            let signed_headers = data_fetcher
+                .get_signed_header_range(start_block, end_block)
+                .await
+                .unwrap();
+            let expected_data_commitment = data_fetcher
+                .get_data_commitment(start_block, end_block)
+                .await;
+            (signed_headers, expected_data_commitment)
+        });
+
+        let mut builder = DefaultBuilder::new();
+
+        log::debug!("Defining circuit");
+        DataCommitmentRedundantCircuit::<NB_MAP_JOBS, BATCH_SIZE, MAX_LEAVES>::define(&mut builder);
+
+        log::debug!("Building circuit");
+        let circuit = builder.build();
+        log::debug!("Done building circuit");
+
+        let mut input = circuit.input();
+        input.evm_write::<U64Variable>(start_block);
+        input.evm_write::<Bytes32Variable>(H256::from_slice(
+            signed_headers[0].header.hash().as_bytes(),
+        ));
+        input.evm_write::<U64Variable>(end_block);
+        input.evm_write::<Bytes32Variable>(H256::from_slice(
+            signed_headers[signed_headers.len() - 1]
+                .header
+                .hash()
+                .as_bytes(),
+        ));
+
+        log::debug!("Generating proof");
+        let (proof, mut output) = rt.block_on(async { circuit.prove_async(&input).await });
+        log::debug!("Done generating proof");
+
+        circuit.verify(&proof, &input, &output);
+
+        let data_commitment = output.evm_read::<Bytes32Variable>();
+        assert_eq!(data_commitment, H256::from(expected_data_commitment));
+    }
+
+    /// DataCommitmentRedundantCircuit, with the heights of the second data commitment off by one.
+    #[derive(Debug, Clone)]
+    struct DataCommitmentRedundantOffByOneCircuit<
+        const NB_MAP_JOBS: usize,
+        const BATCH_SIZE: usize,
+        const MAX_LEAVES: usize,
+    > {
+        _config: usize,
+    }
+
+    impl<const NB_MAP_JOBS: usize, const BATCH_SIZE: usize, const MAX_LEAVES: usize> Circuit
+        for DataCommitmentRedundantOffByOneCircuit<NB_MAP_JOBS, BATCH_SIZE, MAX_LEAVES>
+    {
+        fn define<L: PlonkParameters<D>, const D: usize>(builder: &mut CircuitBuilder<L, D>) where <<L as plonky2x::prelude::PlonkParameters<D>>::Config as plonky2x::prelude::plonky2::plonk::config::GenericConfig<D>>::Hasher: plonky2x::prelude::plonky2::plonk::config::AlgebraicHasher<<L as plonky2x::prelude::PlonkParameters<D>>::Field>{
+            let start_block_number = builder.evm_read::<U64Variable>();
+            let start_header_hash = builder.evm_read::<Bytes32Variable>();
+            let end_block_number = builder.evm_read::<U64Variable>();
+            let end_header_hash = builder.evm_read::<Bytes32Variable>();
+
+            let data_commitment = prove_data_commitment_redundant_with::<
+                L,
+                D,
+                Self,
+                NB_MAP_JOBS,
+                BATCH_SIZE,
+                MAX_LEAVES,
+            >(
+                builder,
+                start_block_number,
+                start_header_hash,
+                end_block_number,
+                end_header_hash,
+                |builder, data_hashes, start_block, nb_leaves| {
+                    let one = builder.constant::<U64Variable>(1u64);
+                    let start_block = builder.add(start_block, one);
+                    let end_block = builder.add(start_block, nb_leaves);
+                    builder.get_data_commitment::<MAX_LEAVES>(data_hashes, start_block, end_block)
+                },
+            );
+
+            builder.evm_write(data_commitment);
+        }
+
+        fn register_generators<L: PlonkParameters<D>, const D: usize>(
+            generator_registry: &mut plonky2x::prelude::HintRegistry<L, D>,
+        ) where
+        <<L as PlonkParameters<D>>::Config as plonky2x::prelude::plonky2::plonk::config::GenericConfig<D>>::Hasher:
+        plonky2x::prelude::plonky2::plonk::config::AlgebraicHasher<L::Field>,
+        {
+            generator_registry.register_async_hint::<DataCommitmentOffchainInputs<BATCH_SIZE>>();
+            generator_registry.register_async_hint::<DataCommitmentOffchainInputs<MAX_LEAVES>>();
+
+            let mr_id = MapReduceGenerator::<
+                L,
+                DataCommitmentSharedCtx,
+                U64Variable,
+                MapReduceSubchainVariable,
+                Self,
+                BATCH_SIZE,
+                D,
+            >::id();
+            generator_registry.register_simple::<MapReduceGenerator<
+                L,
+                DataCommitmentSharedCtx,
+                U64Variable,
+                MapReduceSubchainVariable,
+                Self,
+                BATCH_SIZE,
+                D,
+            >>(mr_id);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    #[should_panic(expected = "was set twice with different values")]
+    fn test_data_commitment_redundant_detects_divergent_path() {
+        env_logger::try_init().unwrap_or_default();
+        dotenv::dotenv().ok();
+
+        const NB_MAP_JOBS: usize = 2;
+        const BATCH_SIZE: usize = 2;
+        const MAX_LEAVES: usize = NB_MAP_JOBS * BATCH_SIZE;
+
+        let start_block = 10000u64;
+        let end_block = 10004u64;
+
+        let mut data_fetcher = InputDataFetcher::default();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let signed_headers = rt.block_on(async {
+            data_fetcher
+                .get_signed_header_range(start_block, end_block)
+                .await
+                .unwrap()
+        });
+
+        let mut builder = DefaultBuilder::new();
+        DataCommitmentRedundantOffByOneCircuit::<NB_MAP_JOBS, BATCH_SIZE, MAX_LEAVES>::define(
+            &mut builder,
+        );
+        let circuit = builder.build();
+
+        let mut input = circuit.input();
+        input.evm_write::<U64Variable>(start_block);
+        input.evm_write::<Bytes32Variable>(H256::from_slice(
+            signed_headers[0].header.hash().as_bytes(),
+        ));
+        input.evm_write::<U64Variable>(end_block);
+        input.evm_write::<Bytes32Variable>(H256::from_slice(
+            signed_headers[signed_headers.len() - 1]
+                .header
+                .hash()
+                .as_bytes(),
+        ));
+
+        // The second data commitment disagrees with the mapreduce one, so proving must fail.
+        rt.block_on(async { circuit.prove_async(&input).await });
+    }
+
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    fn test_data_commitment_with_data_hashes() {
+        env::set_var("RUST_LOG", "debug");
+        env_logger::try_init().unwrap_or_default();
+        dotenv::dotenv().ok();
+
+        const MAX_LEAVES: usize = 4;
+
+        // The window is smaller than MAX_LEAVES, so the last data hash should be zero.
+        let start_block = 10000u64;
+        let end_block = 10003u64;
+
+        let mut data_fetcher = InputDataFetcher::default();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let (signed_headers, expected_data_commitment) = rt.block_on(async {
+            let signed_headers = data_fetcher
+                .get_signed_header_range(start_block, end_block)
+                .await
+                .unwrap();
+            let expected_data_commitment = data_fetcher
+                .get_data_commitment(start_block, end_block)
+                .await;
+            (signed_headers, expected_data_commitment)
+        });
+
+        let mut builder = DefaultBuilder::new();
+
+        log::debug!("Defining circuit");
+        DataCommitmentWithDataHashesCircuit::<MAX_LEAVES>::define(&mut builder);
+
+        log::debug!("Building circuit");
+        let circuit = builder.build();
+        log::debug!("Done building circuit");
+
+        let mut input = circuit.input();
+        input.evm_write::<U64Variable>(start_block);
+        input.evm_write::<Bytes32Variable>(H256::from_slice(
+            signed_headers[0].header.hash().as_bytes(),
+        ));
+        input.evm_write::<U64Variable>(end_block);
+        input.evm_write::<Bytes32Variable>(H256::from_slice(
+            signed_headers[signed_headers.len() - 1]
+                .header
+                .hash()
+                .as_bytes(),
+        ));
+
+        log::debug!("Generating proof");
+        let (proof, output) = rt.block_on(async { circuit.prove_async(&input).await });
+        log::debug!("Done generating proof");
+
+        circuit.verify(&proof, &input, &output);
+
+        // Decode the outputs the way a consumer of the proof would.
+        let (PublicInput::Bytes(input_bytes), PublicOutput::Bytes(output_bytes)) =
+            (&input, &output)
+        else {
+            panic!("expected evm-encoded input and output");
+        };
+        let ProofOutputs::WithLeaves(outputs) =
+            ProofOutputs::decode(input_bytes, output_bytes).unwrap()
+        else {
+            panic!("expected data commitment outputs with leaves");
+        };
+        assert_eq!(outputs.data_commitment, expected_data_commitment.0);
+
+        // The window is smaller than MAX_LEAVES, so the padding leaf is not decoded.
+        assert_eq!(outputs.leaves.len(), (end_block - start_block) as usize);
+        for (i, (block_height, data_hash)) in outputs.leaves.iter().enumerate() {
+            assert_eq!(*block_height, start_block + i as u64);
+            let expected_data_hash = signed_headers[i].header.data_hash.unwrap();
+            assert_eq!(data_hash.as_slice(), expected_data_hash.as_bytes());
+        }
     }
 
     #[test]
@@ -6,7 +6,8 @@ use tendermintx::config::TendermintConfig;
 use tendermintx::skip::{SkipOffchainInputs, TendermintSkipCircuit};
 
 use crate::builder::{DataCommitmentBuilder, DataCommitmentSharedCtx};
-use crate::data_commitment::DataCommitmentOffchainInputs;
+use crate::cache::CircuitLabels;
+use crate::data_commitment::{DataCommitmentOffchainInputs, DataHashProofOffchainInputs};
 use crate::vars::MapReduceSubchainVariable;
 
 #[derive(Debug, Clone)]
@@ -88,6 +89,86 @@ impl<
     }
 }
 
+impl<
+        const MAX_VALIDATOR_SET_SIZE: usize,
+        const CHAIN_ID_SIZE_BYTES: usize,
+        C: TendermintConfig<CHAIN_ID_SIZE_BYTES>,
+        const NB_MAP_JOBS: usize,
+        const BATCH_SIZE: usize,
+    > CircuitLabels
+    for CombinedSkipCircuit<MAX_VALIDATOR_SET_SIZE, CHAIN_ID_SIZE_BYTES, C, NB_MAP_JOBS, BATCH_SIZE>
+{
+    const KIND: &'static str = "header_range";
+    const WINDOW_SIZE: usize = NB_MAP_JOBS * BATCH_SIZE;
+}
+
+/// A lighter-weight alternative to CombinedSkipCircuit for consumers that only need to spot-check
+/// the boundaries of a range. Instead of the data commitment over the range, it outputs the data
+/// hashes of the trusted and target blocks, each proven against its header.
+///
+/// The output is evm-encoded in the following order:
+///     target_header_hash (bytes32), trusted_block (uint64), trusted_data_hash (bytes32),
+///     target_block (uint64), target_data_hash (bytes32)
+#[derive(Debug, Clone)]
+pub struct CombinedSkipDataHashesCircuit<
+    const MAX_VALIDATOR_SET_SIZE: usize,
+    const CHAIN_ID_SIZE_BYTES: usize,
+    C: TendermintConfig<CHAIN_ID_SIZE_BYTES>,
+> {
+    _phantom: std::marker::PhantomData<C>,
+}
+
+impl<
+        const MAX_VALIDATOR_SET_SIZE: usize,
+        const CHAIN_ID_SIZE_BYTES: usize,
+        C: TendermintConfig<CHAIN_ID_SIZE_BYTES>,
+    > Circuit for CombinedSkipDataHashesCircuit<MAX_VALIDATOR_SET_SIZE, CHAIN_ID_SIZE_BYTES, C>
+{
+    fn define<L: PlonkParameters<D>, const D: usize>(builder: &mut CircuitBuilder<L, D>)  where <<L as plonky2x::prelude::PlonkParameters<D>>::Config as plonky2x::prelude::plonky2::plonk::config::GenericConfig<D>>::Hasher: plonky2x::prelude::plonky2::plonk::config::AlgebraicHasher<<L as plonky2x::prelude::PlonkParameters<D>>::Field>{
+        let trusted_block = builder.evm_read::<U64Variable>();
+        let trusted_header_hash = builder.evm_read::<Bytes32Variable>();
+        let target_block = builder.evm_read::<U64Variable>();
+
+        let target_header_hash = builder.skip::<MAX_VALIDATOR_SET_SIZE, CHAIN_ID_SIZE_BYTES>(
+            C::CHAIN_ID_BYTES,
+            C::SKIP_MAX,
+            trusted_block,
+            trusted_header_hash,
+            target_block,
+        );
+
+        let trusted_data_hash = builder.prove_data_hash(trusted_block, trusted_header_hash);
+        let target_data_hash = builder.prove_data_hash(target_block, target_header_hash);
+
+        builder.evm_write(target_header_hash);
+        builder.evm_write(trusted_block);
+        builder.evm_write(trusted_data_hash);
+        builder.evm_write(target_block);
+        builder.evm_write(target_data_hash);
+    }
+
+    fn register_generators<L: PlonkParameters<D>, const D: usize>(
+        generator_registry: &mut plonky2x::prelude::HintRegistry<L, D>,
+    ) where
+        <<L as PlonkParameters<D>>::Config as plonky2x::prelude::plonky2::plonk::config::GenericConfig<D>>::Hasher:
+        plonky2x::prelude::plonky2::plonk::config::AlgebraicHasher<L::Field>,
+    {
+        generator_registry.register_async_hint::<SkipOffchainInputs<MAX_VALIDATOR_SET_SIZE>>();
+        generator_registry.register_async_hint::<DataHashProofOffchainInputs>();
+    }
+}
+
+impl<
+        const MAX_VALIDATOR_SET_SIZE: usize,
+        const CHAIN_ID_SIZE_BYTES: usize,
+        C: TendermintConfig<CHAIN_ID_SIZE_BYTES>,
+    > CircuitLabels
+    for CombinedSkipDataHashesCircuit<MAX_VALIDATOR_SET_SIZE, CHAIN_ID_SIZE_BYTES, C>
+{
+    const KIND: &'static str = "header_range_data_hashes";
+    const WINDOW_SIZE: usize = C::SKIP_MAX;
+}
+
 #[cfg(test)]
 mod tests {
     use std::env;
@@ -96,6 +177,7 @@ mod tests {
     use plonky2x::prelude::{DefaultBuilder, GateRegistry, HintRegistry};
     use subtle_encoding::hex;
     use tendermintx::config::{Mocha4Config, MOCHA_4_CHAIN_ID_SIZE_BYTES};
+    use tendermintx::input::InputDataFetcher;
 
     use super::*;
 
@@ -190,6 +272,78 @@ mod tests {
         println!("data_commitment {:?}", data_commitment);
     }
 
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    fn test_header_range_data_hashes() {
+        env::set_var("RUST_LOG", "debug");
+        env_logger::try_init().unwrap_or_default();
+        dotenv::dotenv().ok();
+
+        const MAX_VALIDATOR_SET_SIZE: usize = 8;
+
+        // These blocks are on Mocha-4 testnet.
+        let start_block = 500u64;
+        let start_header_hash =
+            hex::decode_upper("46604E5FF15811D674CBAF2067DE6479A381EEC1BA046B90508939A685B40AE7")
+                .unwrap();
+        let end_block = 504u64;
+
+        let mut builder = DefaultBuilder::new();
+
+        log::debug!("Defining circuit");
+        CombinedSkipDataHashesCircuit::<
+            MAX_VALIDATOR_SET_SIZE,
+            MOCHA_4_CHAIN_ID_SIZE_BYTES,
+            Mocha4Config,
+        >::define(&mut builder);
+
+        log::debug!("Building circuit");
+        let circuit = builder.build();
+        log::debug!("Done building circuit");
+
+        let mut input = circuit.input();
+        input.evm_write::<U64Variable>(start_block);
+        input.evm_write::<Bytes32Variable>(H256::from_slice(start_header_hash.as_slice()));
+        input.evm_write::<U64Variable>(end_block);
+
+        log::debug!("Generating proof");
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let (proof, mut output) = rt.block_on(async { circuit.prove_async(&input).await });
+        log::debug!("Done generating proof");
+
+        circuit.verify(&proof, &input, &output);
+
+        let data_fetcher = InputDataFetcher::default();
+        let (start_signed_header, end_signed_header) = rt.block_on(async {
+            (
+                data_fetcher
+                    .get_signed_header_from_number(start_block)
+                    .await,
+                data_fetcher.get_signed_header_from_number(end_block).await,
+            )
+        });
+
+        let target_header_hash = output.evm_read::<Bytes32Variable>();
+        assert_eq!(
+            target_header_hash,
+            H256::from_slice(end_signed_header.header.hash().as_bytes())
+        );
+
+        assert_eq!(output.evm_read::<U64Variable>(), start_block);
+        let trusted_data_hash = output.evm_read::<Bytes32Variable>();
+        assert_eq!(
+            trusted_data_hash,
+            H256::from_slice(start_signed_header.header.data_hash.unwrap().as_bytes())
+        );
+
+        assert_eq!(output.evm_read::<U64Variable>(), end_block);
+        let target_data_hash = output.evm_read::<Bytes32Variable>();
+        assert_eq!(
+            target_data_hash,
+            H256::from_slice(end_signed_header.header.data_hash.unwrap().as_bytes())
+        );
+    }
+
     #[test]
     #[cfg_attr(feature = "ci", ignore)]
     fn test_header_range_small() {
@@ -1,20 +1,26 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path::Path;
+use std::sync::Mutex;
 
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use async_trait::async_trait;
 use ethers::types::H256;
-use log::info;
+use log::{info, warn};
 use plonky2x::frontend::merkle::tree::InclusionProof;
 use plonky2x::prelude::RichField;
 use serde::Deserialize;
-use subtle_encoding::hex;
+use sha2::{Digest, Sha256};
 use tendermint::block::signed_header::SignedHeader;
+use tendermint::block::Header;
 use tendermint_proto::types::BlockId as RawBlockId;
 use tendermint_proto::Protobuf;
 use tendermintx::input::tendermint_utils::CommitResponse;
 use tendermintx::input::{InputDataFetcher, InputDataMode};
 
 use crate::consts::*;
+use crate::utils::Hash32;
+use crate::vars::{validate_data_commitment_proofs, DataCommitmentProofValueType};
 
 #[derive(Debug, Deserialize)]
 pub struct DataCommitmentResponse {
@@ -24,51 +30,447 @@ pub struct DataCommitmentResponse {
 #[derive(Debug, Deserialize)]
 pub struct DataCommitment {
     pub data_commitment: String,
+    /// Fields added by newer celestia-core versions. These are ignored, but logged once per field.
+    #[serde(flatten)]
+    pub unknown_fields: BTreeMap<String, serde_json::Value>,
+}
+
+/// Names of unknown RPC response fields which have already been logged.
+static LOGGED_UNKNOWN_FIELDS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Log each unknown field in an RPC response the first time it is seen, which indicates the RPC is
+/// running a newer celestia-core version.
+fn log_unknown_fields(route: &str, unknown_fields: &BTreeMap<String, serde_json::Value>) {
+    let mut logged_fields = LOGGED_UNKNOWN_FIELDS.lock().unwrap();
+    for field in unknown_fields.keys() {
+        if logged_fields.insert(field.clone()) {
+            warn!(
+                "Unknown field {} in {} response, the RPC may be running a newer celestia-core version",
+                field, route
+            );
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct DataCommitmentInputs<F: RichField> {
-    pub start_header_hash: [u8; 32],
-    pub end_header_hash: [u8; 32],
+    pub start_header_hash: Hash32,
+    pub end_header_hash: Hash32,
     pub data_hash_proofs: Vec<InclusionProof<HEADER_PROOF_DEPTH, PROTOBUF_HASH_SIZE_BYTES, F>>,
     pub last_block_id_proofs:
         Vec<InclusionProof<HEADER_PROOF_DEPTH, PROTOBUF_BLOCK_ID_SIZE_BYTES, F>>,
-    pub expected_data_commitment: [u8; 32],
+    pub expected_data_commitment: Hash32,
 }
 
 #[async_trait]
 pub trait DataCommitmentInputFetcher {
-    async fn get_data_commitment(&mut self, start_block: u64, end_block: u64) -> [u8; 32];
+    async fn get_data_commitment(&mut self, start_block: u64, end_block: u64) -> Hash32;
 
     /// Get the latest block number.
     async fn get_latest_block_number(&self) -> u64;
 
-    /// Get signed headers in the range [start_block_number, end_block_number] inclusive.
+    /// Get signed headers in the range [start_block_number, end_block_number] inclusive, one per
+    /// height in order (see fetch_signed_header_range).
     /// Note: Assumes start_block_number and end_block_number are less than or equal to the latest
     /// block number.
     async fn get_signed_header_range(
         &self,
         start_block_number: u64,
         end_block_number: u64,
-    ) -> Vec<SignedHeader>;
+    ) -> Result<Vec<SignedHeader>>;
+
+    /// Get the inclusion proof of a block's data hash against its header.
+    async fn get_data_hash_proof<F: RichField>(
+        &self,
+        block_number: u64,
+    ) -> InclusionProof<HEADER_PROOF_DEPTH, PROTOBUF_HASH_SIZE_BYTES, F>;
+
+    /// Build the data commitment proof inputs for the range [headers[0].height, headers[n-1].height)
+    /// from consecutive headers that are already held locally, padded to MAX_LEAVES.
+    /// Errors if there are fewer than 2 or more than MAX_LEAVES + 1 headers, or if the headers are
+    /// not consecutive and linked by their last_block_id's.
+    fn get_data_commitment_proof_from_headers<const MAX_LEAVES: usize, F: RichField>(
+        &self,
+        headers: &[Header],
+    ) -> Result<DataCommitmentProofValueType<MAX_LEAVES, F>>;
 
     /// start_block_number and end_block_number are not guaranteed to be less than the latest_block.
+    /// Errors with the height of the first header that is not valid (see fetch_header_range_proofs).
     async fn get_data_commitment_inputs<const MAX_LEAVES: usize, F: RichField>(
         &mut self,
         start_block_number: u64,
         end_block_number: u64,
-    ) -> DataCommitmentInputs<F>;
+    ) -> Result<DataCommitmentInputs<F>>;
 }
 
 const MAX_NUM_RETRIES: usize = 3;
 
+// Note: Tested with 500+ concurrent requests, but monitor for any issues.
+const MAX_BATCH_SIZE: usize = 200;
+
+/// Build the inclusion proof of a header field, rejecting proofs that are not HEADER_PROOF_DEPTH deep.
+/// Without this check, a malformed fixture or RPC response only fails later when the proof is
+/// written into a fixed-depth circuit variable.
+pub fn header_inclusion_proof<const LEAF_SIZE_BYTES: usize, F: RichField>(
+    proof: Vec<H256>,
+    leaf: [u8; LEAF_SIZE_BYTES],
+) -> Result<InclusionProof<HEADER_PROOF_DEPTH, LEAF_SIZE_BYTES, F>> {
+    ensure!(
+        proof.len() <= HEADER_PROOF_DEPTH,
+        "header inclusion proof depth {} exceeds the maximum depth {}",
+        proof.len(),
+        HEADER_PROOF_DEPTH
+    );
+    ensure!(
+        proof.len() == HEADER_PROOF_DEPTH,
+        "header inclusion proof depth {} is less than the expected depth {}",
+        proof.len(),
+        HEADER_PROOF_DEPTH
+    );
+    Ok(InclusionProof { proof, leaf })
+}
+
+/// A source of signed headers by height, such as a Tendermint RPC. The header range fetching below
+/// is written against it, so it can be tested with a mock source.
+#[async_trait]
+pub trait SignedHeaderSource {
+    async fn fetch_signed_header(&self, height: u64) -> SignedHeader;
+}
+
+#[async_trait]
+impl SignedHeaderSource for InputDataFetcher {
+    async fn fetch_signed_header(&self, height: u64) -> SignedHeader {
+        self.get_signed_header_from_number(height).await
+    }
+}
+
+/// The heights in [start_height, end_height] that none of headers is at.
+pub fn find_missing_heights(
+    headers: &[SignedHeader],
+    start_height: u64,
+    end_height: u64,
+) -> Vec<u64> {
+    let heights = headers
+        .iter()
+        .map(|signed_header| signed_header.header.height.value())
+        .collect::<BTreeSet<_>>();
+    (start_height..=end_height)
+        .filter(|height| !heights.contains(height))
+        .collect()
+}
+
+/// Sort headers by height and resolve duplicate heights. Copies of a header are dropped. Of
+/// different headers at the same height, the one whose hash is the next header's last_block_id is
+/// kept, where the next header after the highest height is next_header. Errors if a duplicate can't
+/// be resolved. Returns one header per height, and the number of duplicates dropped.
+pub fn resolve_duplicate_heights(
+    mut headers: Vec<SignedHeader>,
+    next_header: Option<&Header>,
+) -> Result<(Vec<SignedHeader>, usize)> {
+    headers.sort_by_key(|signed_header| signed_header.header.height.value());
+
+    let mut nb_duplicates = 0;
+    let mut groups: Vec<Vec<SignedHeader>> = Vec::new();
+    for signed_header in headers {
+        match groups.last_mut() {
+            Some(group) if group[0].header.height == signed_header.header.height => {
+                if group
+                    .iter()
+                    .any(|other| other.header.hash() == signed_header.header.hash())
+                {
+                    nb_duplicates += 1;
+                } else {
+                    group.push(signed_header);
+                }
+            }
+            _ => groups.push(vec![signed_header]),
+        }
+    }
+
+    // Resolve from the highest height down, so the next header of each height is already resolved.
+    let mut resolved: Vec<SignedHeader> = Vec::with_capacity(groups.len());
+    let mut next_header = next_header.cloned();
+    for group in groups.into_iter().rev() {
+        let height = group[0].header.height.value();
+        let nb_candidates = group.len();
+        let signed_header = if nb_candidates == 1 {
+            group.into_iter().next().unwrap()
+        } else {
+            nb_duplicates += nb_candidates - 1;
+            let next_last_block_id = next_header
+                .as_ref()
+                .filter(|next| next.height.value() == height + 1)
+                .and_then(|next| next.last_block_id)
+                .map(|id| id.hash);
+            group
+                .into_iter()
+                .find(|candidate| Some(candidate.header.hash()) == next_last_block_id)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "RPC returned {} different headers at height {}, and header {} is not linked to any of them",
+                        nb_candidates,
+                        height,
+                        height + 1
+                    )
+                })?
+        };
+        next_header = Some(signed_header.header.clone());
+        resolved.push(signed_header);
+    }
+    resolved.reverse();
+    Ok((resolved, nb_duplicates))
+}
+
+/// Fetch the signed header at height, re-fetching up to MAX_NUM_RETRIES times while the source
+/// answers with the header of another height.
+async fn fetch_signed_header_at<S: SignedHeaderSource + Sync>(
+    source: &S,
+    height: u64,
+) -> Result<SignedHeader> {
+    for _ in 0..MAX_NUM_RETRIES {
+        let signed_header = source.fetch_signed_header(height).await;
+        if signed_header.header.height.value() == height {
+            return Ok(signed_header);
+        }
+    }
+    bail!(
+        "RPC did not return header {} in {} attempts",
+        height,
+        MAX_NUM_RETRIES
+    )
+}
+
+/// Fetch the signed headers in [start_height, end_height] from source concurrently. Load-balanced
+/// endpoints occasionally answer with the header of another height, which duplicates one height and
+/// drops another, so the headers are normalized: headers outside the range are dropped, missing
+/// heights are re-fetched individually, and duplicate heights are resolved by
+/// resolve_duplicate_heights (fetching the header after end_height if the last height needs it).
+pub async fn fetch_signed_header_batch<S: SignedHeaderSource + Sync>(
+    source: &S,
+    start_height: u64,
+    end_height: u64,
+) -> Result<Vec<SignedHeader>> {
+    let mut signed_headers = futures::future::join_all(
+        (start_height..=end_height).map(|height| source.fetch_signed_header(height)),
+    )
+    .await;
+    signed_headers.retain(|signed_header| {
+        (start_height..=end_height).contains(&signed_header.header.height.value())
+    });
+
+    let missing_heights = find_missing_heights(&signed_headers, start_height, end_height);
+    if !missing_heights.is_empty() {
+        warn!(
+            "RPC did not return {} height(s) in [{}, {}], re-fetching",
+            missing_heights.len(),
+            start_height,
+            end_height
+        );
+    }
+    for height in missing_heights {
+        signed_headers.push(fetch_signed_header_at(source, height).await?);
+    }
+
+    let last_height_hashes = signed_headers
+        .iter()
+        .filter(|signed_header| signed_header.header.height.value() == end_height)
+        .map(|signed_header| signed_header.header.hash().as_bytes().to_vec())
+        .collect::<BTreeSet<_>>();
+    let next_header = if last_height_hashes.len() > 1 {
+        Some(fetch_signed_header_at(source, end_height + 1).await?.header)
+    } else {
+        None
+    };
+    let (signed_headers, nb_duplicates) =
+        resolve_duplicate_heights(signed_headers, next_header.as_ref())?;
+    if nb_duplicates > 0 {
+        warn!(
+            "RPC returned {} duplicate header(s) in [{}, {}]",
+            nb_duplicates, start_height, end_height
+        );
+    }
+    Ok(signed_headers)
+}
+
+/// Fetch the signed headers in [start_height, end_height] from source, batch_size heights at a time
+/// (see fetch_signed_header_batch). Returns one header per height, in order.
+pub async fn fetch_signed_header_range<S: SignedHeaderSource + Sync>(
+    source: &S,
+    start_height: u64,
+    end_height: u64,
+    batch_size: usize,
+) -> Result<Vec<SignedHeader>> {
+    let mut signed_headers = Vec::new();
+    let mut curr_block = start_height;
+    while curr_block <= end_height {
+        let batch_end_block = std::cmp::min(curr_block + batch_size as u64 - 1, end_height);
+        signed_headers
+            .extend(fetch_signed_header_batch(source, curr_block, batch_end_block).await?);
+        curr_block = batch_end_block + 1;
+    }
+    Ok(signed_headers)
+}
+
+/// Check curr is the header after prev: its height is prev's height + 1, and its last_block_id is
+/// prev's hash. The data commitment circuits check the same link, so this catches a bad header from
+/// the RPC before proving starts.
+pub fn check_header_link(prev: &Header, curr: &Header) -> Result<()> {
+    ensure!(
+        curr.height.value() == prev.height.value() + 1,
+        "headers are not consecutive at height {}",
+        prev.height.value()
+    );
+    ensure!(
+        curr.last_block_id.map(|id| id.hash) == Some(prev.hash()),
+        "header {} is not linked to header {}",
+        curr.height.value(),
+        prev.height.value()
+    );
+    Ok(())
+}
+
+/// Verify the inclusion proof of the header field at index against header_hash. The proof is
+/// ordered from the leaf up, and bit i of index is the side of the node at level i.
+pub fn verify_header_inclusion_proof(
+    header_hash: &[u8],
+    index: usize,
+    leaf: &[u8],
+    proof: &[H256],
+) -> bool {
+    let mut node: [u8; 32] = Sha256::new()
+        .chain_update([0u8])
+        .chain_update(leaf)
+        .finalize()
+        .into();
+    for (level, sibling) in proof.iter().enumerate() {
+        let hasher = Sha256::new().chain_update([1u8]);
+        let hasher = if (index >> level) & 1 == 1 {
+            hasher.chain_update(sibling.as_bytes()).chain_update(node)
+        } else {
+            hasher.chain_update(node).chain_update(sibling.as_bytes())
+        };
+        node = hasher.finalize().into();
+    }
+    node.as_slice() == header_hash
+}
+
+/// The data_hash and last_block_id proofs of a validated header range (see fetch_header_range_proofs).
+pub struct HeaderRangeProofs<F: RichField> {
+    pub start_header_hash: Hash32,
+    pub end_header_hash: Hash32,
+    /// The data_hash proofs of the headers in [start_height, end_height).
+    pub data_hash_proofs: Vec<InclusionProof<HEADER_PROOF_DEPTH, PROTOBUF_HASH_SIZE_BYTES, F>>,
+    /// The last_block_id proofs of the headers in (start_height, end_height].
+    pub last_block_id_proofs:
+        Vec<InclusionProof<HEADER_PROOF_DEPTH, PROTOBUF_BLOCK_ID_SIZE_BYTES, F>>,
+}
+
+/// Fetch the headers in [start_height, end_height] from source, batch_size heights at a time, and
+/// validate each batch before the next one is fetched: every header must be linked to the one
+/// before it, and its data_hash and last_block_id proofs (built by fetcher) must verify against its
+/// hash. The first invalid header aborts the fetch with an error naming its height.
+pub async fn fetch_header_range_proofs<S: SignedHeaderSource + Sync, F: RichField>(
+    source: &S,
+    fetcher: &InputDataFetcher,
+    start_height: u64,
+    end_height: u64,
+    batch_size: usize,
+) -> Result<HeaderRangeProofs<F>> {
+    let mut range_proofs = HeaderRangeProofs {
+        start_header_hash: Hash32::default(),
+        end_header_hash: Hash32::default(),
+        data_hash_proofs: Vec::new(),
+        last_block_id_proofs: Vec::new(),
+    };
+    let mut prev_header: Option<Header> = None;
+    let mut curr_block = start_height;
+    while curr_block <= end_height {
+        let batch_end_block = std::cmp::min(curr_block + batch_size as u64 - 1, end_height);
+        for signed_header in fetch_signed_header_batch(source, curr_block, batch_end_block).await? {
+            let header = signed_header.header;
+            let height = header.height.value();
+            if let Some(prev_header) = &prev_header {
+                check_header_link(prev_header, &header)?;
+            }
+            let header_hash = header.hash();
+
+            // Don't include the data hash and corresponding proof of end_block, as the circuit's
+            // data_commitment is computed over the range [start_block, end_block - 1].
+            if height < end_height {
+                let data_hash = header
+                    .data_hash
+                    .ok_or_else(|| anyhow!("header {} has no data_hash", height))?;
+                let data_hash_proof = fetcher.get_inclusion_proof::<PROTOBUF_HASH_SIZE_BYTES, F>(
+                    &header,
+                    DATA_HASH_INDEX as u64,
+                    data_hash.encode_vec(),
+                );
+                ensure!(
+                    verify_header_inclusion_proof(
+                        header_hash.as_bytes(),
+                        DATA_HASH_INDEX,
+                        &data_hash_proof.leaf,
+                        &data_hash_proof.proof
+                    ),
+                    "data_hash proof of header {} does not verify against its hash",
+                    height
+                );
+                range_proofs.data_hash_proofs.push(
+                    header_inclusion_proof(data_hash_proof.proof, data_hash_proof.leaf)
+                        .with_context(|| format!("data_hash proof of header {}", height))?,
+                );
+            }
+
+            // Don't include last_block_id of start, as the data_commitment circuit only requires
+            // the last block id's of blocks in the range [start_block + 1, end_block]. Specifically,
+            // the circuit needs the last_block_id proofs of data_commitment range shifted by one
+            // block to the right. This also means the null last_block_id of the genesis block
+            // (height 1) is never fetched, even for ranges starting at height 1.
+            if height > start_height {
+                let last_block_id_proof = fetcher
+                    .get_inclusion_proof::<PROTOBUF_BLOCK_ID_SIZE_BYTES, F>(
+                        &header,
+                        LAST_BLOCK_ID_INDEX as u64,
+                        Protobuf::<RawBlockId>::encode_vec(
+                            header.last_block_id.unwrap_or_default(),
+                        ),
+                    );
+                ensure!(
+                    verify_header_inclusion_proof(
+                        header_hash.as_bytes(),
+                        LAST_BLOCK_ID_INDEX,
+                        &last_block_id_proof.leaf,
+                        &last_block_id_proof.proof
+                    ),
+                    "last_block_id proof of header {} does not verify against its hash",
+                    height
+                );
+                range_proofs.last_block_id_proofs.push(
+                    header_inclusion_proof(last_block_id_proof.proof, last_block_id_proof.leaf)
+                        .with_context(|| format!("last_block_id proof of header {}", height))?,
+                );
+            }
+
+            if height == start_height {
+                range_proofs.start_header_hash = Hash32::try_from(header_hash)?;
+            }
+            if height == end_height {
+                range_proofs.end_header_hash = Hash32::try_from(header_hash)?;
+            }
+            prev_header = Some(header);
+        }
+        curr_block = batch_end_block + 1;
+    }
+    Ok(range_proofs)
+}
+
 #[async_trait]
 impl DataCommitmentInputFetcher for InputDataFetcher {
-    async fn get_data_commitment(&mut self, start_block: u64, end_block: u64) -> [u8; 32] {
+    async fn get_data_commitment(&mut self, start_block: u64, end_block: u64) -> Hash32 {
         // If start_block == end_block, then return a dummy commitment.
         // This will occur in the context of data commitment's map reduce when leaves that contain blocks beyond the end_block.
         if end_block <= start_block {
-            return [0u8; 32];
+            return Hash32::default();
         }
 
         let file_name = format!(
@@ -102,11 +504,9 @@ impl DataCommitmentInputFetcher for InputDataFetcher {
         };
         let v: DataCommitmentResponse =
             serde_json::from_str(&fetched_result).expect("Failed to parse JSON");
+        log_unknown_fields("data_commitment", &v.result.unknown_fields);
 
-        hex::decode_upper(v.result.data_commitment)
-            .unwrap()
-            .try_into()
-            .unwrap()
+        Hash32::from_rpc_hex(&v.result.data_commitment).unwrap()
     }
 
     async fn get_latest_block_number(&self) -> u64 {
@@ -121,101 +521,124 @@ impl DataCommitmentInputFetcher for InputDataFetcher {
         &self,
         start_block_number: u64,
         end_block_number: u64,
-    ) -> Vec<SignedHeader> {
-        // Note: Tested with 500+ concurrent requests, but monitor for any issues.
-        const MAX_BATCH_SIZE: usize = 200;
-
-        let mut signed_headers = Vec::new();
-        let mut curr_block = start_block_number;
-        while curr_block <= end_block_number {
-            let batch_end_block =
-                std::cmp::min(curr_block + MAX_BATCH_SIZE as u64, end_block_number + 1);
-            // Batch request the headers in the range [curr_block, batch_end_block).
-            let batch_signed_header_futures = (curr_block..batch_end_block)
-                .map(|i| self.get_signed_header_from_number(i))
-                .collect::<Vec<_>>();
-            let batch_signed_headers: Vec<SignedHeader> =
-                futures::future::join_all(batch_signed_header_futures).await;
-            signed_headers.extend(batch_signed_headers);
-
-            curr_block += MAX_BATCH_SIZE as u64;
-        }
+    ) -> Result<Vec<SignedHeader>> {
+        fetch_signed_header_range(self, start_block_number, end_block_number, MAX_BATCH_SIZE).await
+    }
 
-        signed_headers
+    async fn get_data_hash_proof<F: RichField>(
+        &self,
+        block_number: u64,
+    ) -> InclusionProof<HEADER_PROOF_DEPTH, PROTOBUF_HASH_SIZE_BYTES, F> {
+        let signed_header = self.get_signed_header_from_number(block_number).await;
+        let data_hash = signed_header.header.data_hash.unwrap();
+
+        let data_hash_proof = self.get_inclusion_proof::<PROTOBUF_HASH_SIZE_BYTES, F>(
+            &signed_header.header,
+            DATA_HASH_INDEX as u64,
+            data_hash.encode_vec(),
+        );
+        header_inclusion_proof(data_hash_proof.proof, data_hash_proof.leaf).unwrap()
     }
 
-    // start_block_number and end_block_number are not guaranteed to be less than the latest_block.
-    // Fetch the latest block number, and use it to determine the actual range of signed headers to fetch.
-    async fn get_data_commitment_inputs<const MAX_LEAVES: usize, F: RichField>(
-        &mut self,
-        start_block_number: u64,
-        end_block_number: u64,
-    ) -> DataCommitmentInputs<F> {
-        assert!(end_block_number - start_block_number <= MAX_LEAVES as u64);
+    fn get_data_commitment_proof_from_headers<const MAX_LEAVES: usize, F: RichField>(
+        &self,
+        headers: &[Header],
+    ) -> Result<DataCommitmentProofValueType<MAX_LEAVES, F>> {
+        ensure!(
+            headers.len() >= 2 && headers.len() <= MAX_LEAVES + 1,
+            "expected between 2 and {} headers, got {}",
+            MAX_LEAVES + 1,
+            headers.len()
+        );
+        for window in headers.windows(2) {
+            check_header_link(&window[0], &window[1])?;
+        }
 
         let mut data_hash_proofs = Vec::new();
         let mut last_block_id_proofs = Vec::new();
-
-        // Only request up to latest_block_number - 2 (avoid RPC inconsistency).
-        let latest_block_number = self.get_latest_block_number().await;
-        let latest_safe_block_number = latest_block_number - 2;
-        let request_end_block_number = std::cmp::min(end_block_number, latest_safe_block_number);
-        let signed_headers = self
-            .get_signed_header_range(start_block_number, request_end_block_number)
-            .await;
-
-        for i in start_block_number..request_end_block_number + 1 {
-            let signed_header = &signed_headers[(i - start_block_number) as usize];
-
-            // Don't include the data hash and corresponding proof of end_block, as the circuit's
-            // data_commitment is computed over the range [start_block, end_block - 1].
-            if i < request_end_block_number {
-                let data_hash = signed_header.header.data_hash.unwrap();
-
+        for (i, header) in headers.iter().enumerate() {
+            // The data hash of the last header is not in the data commitment range.
+            if i < headers.len() - 1 {
                 let data_hash_proof = self.get_inclusion_proof::<PROTOBUF_HASH_SIZE_BYTES, F>(
-                    &signed_header.header,
+                    header,
                     DATA_HASH_INDEX as u64,
-                    data_hash.encode_vec(),
+                    header.data_hash.unwrap().encode_vec(),
                 );
-                data_hash_proofs.push(data_hash_proof);
+                data_hash_proofs.push(header_inclusion_proof(
+                    data_hash_proof.proof,
+                    data_hash_proof.leaf,
+                )?);
             }
 
-            // Don't include last_block_id of start, as the data_commitment circuit only requires
-            // the last block id's of blocks in the range [start_block + 1, end_block]. Specifically,
-            // the circuit needs the last_block_id proofs of data_commitment range shifted by one
-            // block to the right.
-            if i > start_block_number {
+            // The last_block_id of the first header is not needed to link the range.
+            if i > 0 {
                 let last_block_id_proof = self
                     .get_inclusion_proof::<PROTOBUF_BLOCK_ID_SIZE_BYTES, F>(
-                        &signed_header.header,
+                        header,
                         LAST_BLOCK_ID_INDEX as u64,
-                        Protobuf::<RawBlockId>::encode_vec(
-                            signed_header.header.last_block_id.unwrap_or_default(),
-                        ),
+                        Protobuf::<RawBlockId>::encode_vec(header.last_block_id.unwrap()),
                     );
-                last_block_id_proofs.push(last_block_id_proof);
+                last_block_id_proofs.push(header_inclusion_proof(
+                    last_block_id_proof.proof,
+                    last_block_id_proof.leaf,
+                )?);
             }
         }
 
-        let mut data_hash_proofs_formatted = data_hash_proofs
-            .into_iter()
-            .map(
-                |proof| InclusionProof::<HEADER_PROOF_DEPTH, PROTOBUF_HASH_SIZE_BYTES, F> {
-                    proof: proof.proof,
-                    leaf: proof.leaf,
-                },
-            )
-            .collect::<Vec<_>>();
+        // Extend data_hash_proofs and last_block_id_proofs to length MAX_LEAVES.
+        for _ in data_hash_proofs.len()..MAX_LEAVES {
+            data_hash_proofs.push(InclusionProof {
+                proof: [H256::zero(); HEADER_PROOF_DEPTH].to_vec(),
+                leaf: [0u8; PROTOBUF_HASH_SIZE_BYTES],
+            });
+            last_block_id_proofs.push(InclusionProof {
+                proof: [H256::zero(); HEADER_PROOF_DEPTH].to_vec(),
+                leaf: [0u8; PROTOBUF_BLOCK_ID_SIZE_BYTES],
+            });
+        }
 
-        let mut last_block_id_proofs_formatted = last_block_id_proofs
-            .into_iter()
-            .map(
-                |proof| InclusionProof::<HEADER_PROOF_DEPTH, PROTOBUF_BLOCK_ID_SIZE_BYTES, F> {
-                    proof: proof.proof,
-                    leaf: proof.leaf,
-                },
-            )
-            .collect::<Vec<_>>();
+        let data_comm_proof = DataCommitmentProofValueType {
+            start_header: Hash32::try_from(headers[0].hash())?.into(),
+            end_header: Hash32::try_from(headers[headers.len() - 1].hash())?.into(),
+            data_hash_proofs,
+            last_block_id_proofs,
+        };
+        data_comm_proof.validate()?;
+        Ok(data_comm_proof)
+    }
+
+    // start_block_number and end_block_number are not guaranteed to be less than the latest_block.
+    // Fetch the latest block number, and use it to determine the actual range of signed headers to fetch.
+    async fn get_data_commitment_inputs<const MAX_LEAVES: usize, F: RichField>(
+        &mut self,
+        start_block_number: u64,
+        end_block_number: u64,
+    ) -> Result<DataCommitmentInputs<F>> {
+        ensure!(
+            end_block_number - start_block_number <= MAX_LEAVES as u64,
+            "range [{}, {}] exceeds the maximum of {} leaves",
+            start_block_number,
+            end_block_number,
+            MAX_LEAVES
+        );
+
+        // Only request up to latest_block_number - 2 (avoid RPC inconsistency).
+        let latest_block_number = self.get_latest_block_number().await;
+        let latest_safe_block_number = latest_block_number - 2;
+        let request_end_block_number = std::cmp::min(end_block_number, latest_safe_block_number);
+        let HeaderRangeProofs {
+            mut start_header_hash,
+            mut end_header_hash,
+            data_hash_proofs: mut data_hash_proofs_formatted,
+            last_block_id_proofs: mut last_block_id_proofs_formatted,
+        } = fetch_header_range_proofs::<_, F>(
+            &*self,
+            &*self,
+            start_block_number,
+            request_end_block_number,
+            MAX_BATCH_SIZE,
+        )
+        .await?;
 
         let num_so_far = data_hash_proofs_formatted.len();
         // Extend data_hash_proofs and last_block_id_proofs to length MAX_LEAVES.
@@ -238,46 +661,45 @@ impl DataCommitmentInputFetcher for InputDataFetcher {
             });
         }
 
+        // Checked here rather than in the hint, so an RPC response of the wrong shape is an error
+        // for the caller instead of a panic inside the witness writer.
+        validate_data_commitment_proofs::<MAX_LEAVES, F>(
+            &data_hash_proofs_formatted,
+            &last_block_id_proofs_formatted,
+        )?;
+
         // Fetch the expected data commitment.
         let expected_data_commitment = self
             .get_data_commitment(start_block_number, request_end_block_number)
             .await;
 
-        let mut start_header = [0u8; 32];
-        let mut end_header = [0u8; 32];
         // If start_block_number >= end_block_number, then start_header and end_header are dummy values.
-        if start_block_number < request_end_block_number {
-            start_header = signed_headers[0]
-                .header
-                .hash()
-                .as_bytes()
-                .try_into()
-                .unwrap();
-            end_header = signed_headers[signed_headers.len() - 1]
-                .header
-                .hash()
-                .as_bytes()
-                .try_into()
-                .unwrap();
+        if start_block_number >= request_end_block_number {
+            start_header_hash = Hash32::default();
+            end_header_hash = Hash32::default();
         }
 
-        DataCommitmentInputs {
-            start_header_hash: start_header,
-            end_header_hash: end_header,
+        Ok(DataCommitmentInputs {
+            start_header_hash,
+            end_header_hash,
             data_hash_proofs: data_hash_proofs_formatted,
             last_block_id_proofs: last_block_id_proofs_formatted,
             expected_data_commitment,
-        }
+        })
     }
 }
 #[cfg(test)]
 mod tests {
 
+    use std::collections::VecDeque;
     use std::env;
 
     use plonky2x::backend::circuit::{DefaultParameters, PlonkParameters};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
 
     use super::*;
+    use crate::builder::tests::generate_synthetic_header_chain;
 
     const D: usize = 2;
     type L = DefaultParameters;
@@ -293,8 +715,423 @@ mod tests {
         let mut fetcher = InputDataFetcher::default();
         let start_block = 3000000;
         let end_block = 3000010;
-        let _ = fetcher
+        fetcher
             .get_data_commitment_inputs::<32, F>(start_block, end_block)
-            .await;
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_data_commitment_response_fields() {
+        // Fields added by newer celestia-core versions are tolerated.
+        let response = r#"{
+            "jsonrpc": "2.0",
+            "id": -1,
+            "result": {
+                "data_commitment": "A0123D5E4B8B8888A61F931EE2252D83568B97C223E0ECA9795B29B8BD8CBA2D",
+                "future_field": {"nested": [1, 2]}
+            }
+        }"#;
+        let v: DataCommitmentResponse = serde_json::from_str(response).unwrap();
+        assert_eq!(
+            Hash32::from_rpc_hex(&v.result.data_commitment).unwrap().0[0],
+            0xa0
+        );
+        assert!(v.result.unknown_fields.contains_key("future_field"));
+        log_unknown_fields("data_commitment", &v.result.unknown_fields);
+
+        // A missing data_commitment is still an error naming the field.
+        let response = r#"{"jsonrpc": "2.0", "id": -1, "result": {"future_field": 1}}"#;
+        let err = serde_json::from_str::<DataCommitmentResponse>(response).unwrap_err();
+        assert!(err.to_string().contains("missing field `data_commitment`"));
+    }
+
+    // Feed random and truncated inputs to the RPC response deserializers, and check they return an
+    // error rather than panicking on malformed or adversarial responses. The seed is fixed so
+    // failures are reproducible; change FUZZ_SEED or FUZZ_ITERATIONS to explore further.
+    #[test]
+    fn test_fuzz_input_deserializers() {
+        const FUZZ_SEED: u64 = 0;
+        const FUZZ_ITERATIONS: usize = 10000;
+        let mut rng = StdRng::seed_from_u64(FUZZ_SEED);
+
+        let valid_response = r#"{"jsonrpc":"2.0","id":-1,"result":{"data_commitment":"A0123D5E4B8B8888A61F931EE2252D83568B97C223E0ECA9795B29B8BD8CBA2D"}}"#;
+
+        for _ in 0..FUZZ_ITERATIONS {
+            // Random bytes, interpreted as a (lossy) UTF-8 string.
+            let len = rng.gen_range(0..128);
+            let mut bytes = vec![0u8; len];
+            rng.fill(&mut bytes[..]);
+            let random_str = String::from_utf8_lossy(&bytes);
+            let _ = Hash32::from_rpc_hex(&random_str);
+            let _ = serde_json::from_str::<DataCommitmentResponse>(&random_str);
+
+            // Random strings of hash-like lengths over the hex and base64 alphabets.
+            const ALPHABET: &[u8] =
+                b"0123456789ABCDEFabcdefghijklmnopqrstuvwxyzGHIJKLMNOPQRSTUVWXYZ+/=";
+            let len = [43, 44, 45, 63, 64, 65][rng.gen_range(0..6)];
+            let hash_like = (0..len)
+                .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+                .collect::<String>();
+            let _ = Hash32::from_rpc_hex(&hash_like);
+
+            // Truncated and corrupted versions of a valid response.
+            let mut response = valid_response.as_bytes().to_vec();
+            response.truncate(rng.gen_range(0..=response.len()));
+            if !response.is_empty() {
+                let idx = rng.gen_range(0..response.len());
+                response[idx] = rng.gen();
+            }
+            if let Ok(v) =
+                serde_json::from_str::<DataCommitmentResponse>(&String::from_utf8_lossy(&response))
+            {
+                let _ = Hash32::from_rpc_hex(&v.result.data_commitment);
+            }
+        }
+    }
+
+    #[test]
+    fn test_header_inclusion_proof_depth() {
+        let leaf = [0u8; PROTOBUF_HASH_SIZE_BYTES];
+
+        assert!(header_inclusion_proof::<PROTOBUF_HASH_SIZE_BYTES, F>(
+            vec![H256::zero(); HEADER_PROOF_DEPTH],
+            leaf
+        )
+        .is_ok());
+
+        let err = header_inclusion_proof::<PROTOBUF_HASH_SIZE_BYTES, F>(
+            vec![H256::zero(); HEADER_PROOF_DEPTH + 1],
+            leaf,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "header inclusion proof depth 5 exceeds the maximum depth 4"
+        );
+
+        let err = header_inclusion_proof::<PROTOBUF_HASH_SIZE_BYTES, F>(
+            vec![H256::zero(); HEADER_PROOF_DEPTH - 1],
+            leaf,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "header inclusion proof depth 3 is less than the expected depth 4"
+        );
+    }
+
+    // Ensure inputs built from locally held headers match the inputs built from the RPC.
+    #[cfg_attr(feature = "ci", ignore)]
+    #[tokio::test]
+    async fn test_get_data_commitment_proof_from_headers() {
+        env_logger::try_init().unwrap_or_default();
+        dotenv::dotenv().ok();
+        const MAX_LEAVES: usize = 4;
+        let mut fetcher = InputDataFetcher::default();
+        let start_block = 10000;
+        let end_block = 10004;
+
+        let headers = fetcher
+            .get_signed_header_range(start_block, end_block)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|signed_header| signed_header.header)
+            .collect::<Vec<_>>();
+        let proof = fetcher
+            .get_data_commitment_proof_from_headers::<MAX_LEAVES, F>(&headers)
+            .unwrap();
+
+        let inputs = fetcher
+            .get_data_commitment_inputs::<MAX_LEAVES, F>(start_block, end_block)
+            .await
+            .unwrap();
+        assert_eq!(proof.start_header, H256::from(inputs.start_header_hash));
+        assert_eq!(proof.end_header, H256::from(inputs.end_header_hash));
+        for i in 0..MAX_LEAVES {
+            assert_eq!(
+                proof.data_hash_proofs[i].leaf,
+                inputs.data_hash_proofs[i].leaf
+            );
+            assert_eq!(
+                proof.data_hash_proofs[i].proof,
+                inputs.data_hash_proofs[i].proof
+            );
+            assert_eq!(
+                proof.last_block_id_proofs[i].leaf,
+                inputs.last_block_id_proofs[i].leaf
+            );
+            assert_eq!(
+                proof.last_block_id_proofs[i].proof,
+                inputs.last_block_id_proofs[i].proof
+            );
+        }
+
+        // Headers that skip a block are rejected.
+        let skipped_headers = vec![headers[0].clone(), headers[2].clone()];
+        assert!(fetcher
+            .get_data_commitment_proof_from_headers::<MAX_LEAVES, F>(&skipped_headers)
+            .is_err());
+    }
+
+    #[test]
+    fn test_check_header_link() {
+        let headers = generate_synthetic_header_chain(3, false);
+        check_header_link(&headers[0], &headers[1]).unwrap();
+        check_header_link(&headers[1], &headers[2]).unwrap();
+
+        assert_eq!(
+            check_header_link(&headers[0], &headers[2])
+                .unwrap_err()
+                .to_string(),
+            "headers are not consecutive at height 10000"
+        );
+
+        // A header whose last_block_id is not its parent's hash.
+        let mut unlinked_header = headers[2].clone();
+        unlinked_header.last_block_id = headers[1].last_block_id;
+        assert_eq!(
+            check_header_link(&headers[1], &unlinked_header)
+                .unwrap_err()
+                .to_string(),
+            "header 10002 is not linked to header 10001"
+        );
+    }
+
+    /// A header source serving a synthetic chain. Heights with override answers serve those once
+    /// each, in order, before falling back to the chain, and every request is logged.
+    struct MockHeaderSource {
+        chain: Vec<SignedHeader>,
+        overrides: Mutex<BTreeMap<u64, VecDeque<SignedHeader>>>,
+        requests: Mutex<Vec<u64>>,
+    }
+
+    impl MockHeaderSource {
+        fn new(chain: Vec<SignedHeader>) -> Self {
+            Self {
+                chain,
+                overrides: Mutex::new(BTreeMap::new()),
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn answer_once(&self, height: u64, signed_header: SignedHeader) {
+            self.overrides
+                .lock()
+                .unwrap()
+                .entry(height)
+                .or_default()
+                .push_back(signed_header);
+        }
+
+        fn nb_requests(&self) -> usize {
+            self.requests.lock().unwrap().len()
+        }
+    }
+
+    #[async_trait]
+    impl SignedHeaderSource for MockHeaderSource {
+        async fn fetch_signed_header(&self, height: u64) -> SignedHeader {
+            self.requests.lock().unwrap().push(height);
+            if let Some(answer) = self
+                .overrides
+                .lock()
+                .unwrap()
+                .get_mut(&height)
+                .and_then(|answers| answers.pop_front())
+            {
+                return answer;
+            }
+            let start_height = self.chain[0].header.height.value();
+            self.chain[(height - start_height) as usize].clone()
+        }
+    }
+
+    /// The synthetic header chain starting at 10000, with the commit of the block 10000 fixture.
+    fn synthetic_signed_header_chain(num_headers: usize) -> Vec<SignedHeader> {
+        let fixture = fs::read_to_string("circuits/fixtures/mocha-4/10000/signed_block.json")
+            .expect("failed to read signed block fixture");
+        let response: serde_json::Value = serde_json::from_str(&fixture).unwrap();
+        let template: SignedHeader = serde_json::from_value(serde_json::json!({
+            "header": response["result"]["header"],
+            "commit": response["result"]["commit"],
+        }))
+        .unwrap();
+        generate_synthetic_header_chain(num_headers, false)
+            .into_iter()
+            .map(|header| {
+                let mut signed_header = template.clone();
+                signed_header.header = header;
+                signed_header
+            })
+            .collect()
+    }
+
+    /// A header at the same height as signed_header, with a different hash.
+    fn fork(signed_header: &SignedHeader) -> SignedHeader {
+        let mut forked = signed_header.clone();
+        forked.header.version.app += 1;
+        forked
+    }
+
+    #[test]
+    fn test_find_missing_heights() {
+        let headers = synthetic_signed_header_chain(4);
+        assert!(find_missing_heights(&headers, 10000, 10003).is_empty());
+
+        // 10001 is returned twice, and 10002 is missing.
+        let duplicated_headers = vec![
+            headers[0].clone(),
+            headers[1].clone(),
+            headers[1].clone(),
+            headers[3].clone(),
+        ];
+        assert_eq!(
+            find_missing_heights(&duplicated_headers, 10000, 10003),
+            vec![10002]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_signed_header_range_normalizes_rpc_answers() {
+        env_logger::try_init().unwrap_or_default();
+        let chain = synthetic_signed_header_chain(10);
+        let source = MockHeaderSource::new(chain.clone());
+        // With a batch size of 4, the batches are [10000, 10003], [10004, 10007] and [10008, 10008].
+        // A fork of 10001, resolved by the link from 10002.
+        source.answer_once(10000, fork(&chain[1]));
+        // A fork of 10003 at the end of its batch, resolved by fetching 10004.
+        source.answer_once(10002, fork(&chain[3]));
+        // A copy of 10005.
+        source.answer_once(10006, chain[5].clone());
+        // A header from the previous batch, which is dropped.
+        source.answer_once(10007, chain[2].clone());
+
+        let signed_headers = fetch_signed_header_range(&source, 10000, 10008, 4)
+            .await
+            .unwrap();
+        assert_eq!(signed_headers, chain[..9].to_vec());
+        // 9 heights, 4 re-fetches of the displaced heights, and 1 fetch of 10004 to resolve 10003.
+        assert_eq!(source.nb_requests(), 14);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_signed_header_range_unresolvable_duplicate() {
+        env_logger::try_init().unwrap_or_default();
+        let chain = synthetic_signed_header_chain(5);
+        let source = MockHeaderSource::new(chain.clone());
+        // Two forks of 10003, neither of which 10004 links to.
+        let mut other_fork = fork(&chain[3]);
+        other_fork.header.version.app += 1;
+        source.answer_once(10002, fork(&chain[3]));
+        source.answer_once(10003, other_fork);
+
+        assert_eq!(
+            fetch_signed_header_range(&source, 10000, 10003, 4)
+                .await
+                .unwrap_err()
+                .to_string(),
+            "RPC returned 2 different headers at height 10003, and header 10004 is not linked to any of them"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_signed_header_range_persistent_missing_height() {
+        env_logger::try_init().unwrap_or_default();
+        let chain = synthetic_signed_header_chain(4);
+        let source = MockHeaderSource::new(chain.clone());
+        // 10001 is answered with 10000 on the batch request and on every re-fetch.
+        for _ in 0..=MAX_NUM_RETRIES {
+            source.answer_once(10001, chain[0].clone());
+        }
+
+        assert_eq!(
+            fetch_signed_header_range(&source, 10000, 10003, 4)
+                .await
+                .unwrap_err()
+                .to_string(),
+            "RPC did not return header 10001 in 3 attempts"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_header_range_proofs() {
+        env_logger::try_init().unwrap_or_default();
+        const MAX_LEAVES: usize = 8;
+        let chain = synthetic_signed_header_chain(6);
+        let source = MockHeaderSource::new(chain.clone());
+        let fetcher = InputDataFetcher::default();
+
+        let range_proofs = fetch_header_range_proofs::<_, F>(&source, &fetcher, 10000, 10005, 4)
+            .await
+            .unwrap();
+        let headers = chain
+            .iter()
+            .map(|signed_header| signed_header.header.clone())
+            .collect::<Vec<_>>();
+        let proof = fetcher
+            .get_data_commitment_proof_from_headers::<MAX_LEAVES, F>(&headers)
+            .unwrap();
+        assert_eq!(
+            proof.start_header,
+            H256::from(range_proofs.start_header_hash)
+        );
+        assert_eq!(proof.end_header, H256::from(range_proofs.end_header_hash));
+        assert_eq!(range_proofs.data_hash_proofs.len(), 5);
+        assert_eq!(range_proofs.last_block_id_proofs.len(), 5);
+        for i in 0..5 {
+            assert_eq!(
+                proof.data_hash_proofs[i].leaf,
+                range_proofs.data_hash_proofs[i].leaf
+            );
+            assert_eq!(
+                proof.data_hash_proofs[i].proof,
+                range_proofs.data_hash_proofs[i].proof
+            );
+            assert_eq!(
+                proof.last_block_id_proofs[i].proof,
+                range_proofs.last_block_id_proofs[i].proof
+            );
+        }
+
+        // A proof with a tampered sibling does not verify.
+        let data_hash_proof = &range_proofs.data_hash_proofs[0];
+        let header_hash = chain[0].header.hash();
+        assert!(verify_header_inclusion_proof(
+            header_hash.as_bytes(),
+            DATA_HASH_INDEX,
+            &data_hash_proof.leaf,
+            &data_hash_proof.proof
+        ));
+        let mut tampered_proof = data_hash_proof.proof.clone();
+        tampered_proof[1] = H256::zero();
+        assert!(!verify_header_inclusion_proof(
+            header_hash.as_bytes(),
+            DATA_HASH_INDEX,
+            &data_hash_proof.leaf,
+            &tampered_proof
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_header_range_proofs_aborts_at_first_invalid_header() {
+        env_logger::try_init().unwrap_or_default();
+        let mut chain = synthetic_signed_header_chain(13);
+        // 10005 is modified after the chain was linked, so 10006 is not linked to it.
+        chain[5].header.version.app += 1;
+        let source = MockHeaderSource::new(chain);
+        let fetcher = InputDataFetcher::default();
+
+        let err = fetch_header_range_proofs::<_, F>(&source, &fetcher, 10000, 10012, 4)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "header 10006 is not linked to header 10005"
+        );
+        // Only the batches [10000, 10003] and [10004, 10007] were fetched.
+        assert_eq!(source.nb_requests(), 8);
     }
 }
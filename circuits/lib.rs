@@ -1,11 +1,17 @@
 #![allow(clippy::needless_range_loop)]
 #![allow(clippy::too_many_arguments)]
 
+pub mod attestation;
 pub mod builder;
+pub mod cache;
+pub mod checkpoints;
 pub mod config;
 pub mod consts;
 pub mod data_commitment;
 pub mod header_range;
 pub mod input;
 pub mod next_header;
+pub mod public_values;
+pub mod utils;
+pub mod validator;
 pub mod vars;
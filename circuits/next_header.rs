@@ -5,6 +5,7 @@ use tendermintx::config::TendermintConfig;
 use tendermintx::step::{StepOffchainInputs, TendermintStepCircuit};
 
 use crate::builder::DataCommitmentBuilder;
+use crate::cache::CircuitLabels;
 use crate::data_commitment::DataCommitmentOffchainInputs;
 
 #[derive(Debug, Clone)]
@@ -57,6 +58,16 @@ impl<
     }
 }
 
+impl<
+        const MAX_VALIDATOR_SET_SIZE: usize,
+        const CHAIN_ID_SIZE_BYTES: usize,
+        C: TendermintConfig<CHAIN_ID_SIZE_BYTES>,
+    > CircuitLabels for CombinedStepCircuit<MAX_VALIDATOR_SET_SIZE, CHAIN_ID_SIZE_BYTES, C>
+{
+    const KIND: &'static str = "next_header";
+    const WINDOW_SIZE: usize = 1;
+}
+
 #[cfg(test)]
 mod tests {
     use std::env;
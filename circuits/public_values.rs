@@ -0,0 +1,1039 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use alloy_sol_types::{sol, SolType};
+use anyhow::{anyhow, ensure, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::data_commitment::DATA_HASH_OUTPUTS_MAX_LEAVES;
+
+/// The number of bytes in PublicValuesV1::to_bytes.
+pub const PUBLIC_VALUES_V1_SIZE_BYTES: usize = 112;
+
+type PublicValuesV1Tuple = sol! { tuple(bytes32, uint64, bytes32, uint64, bytes32) };
+
+/// A named field of a circuit's evm-encoded input or output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoField {
+    pub name: &'static str,
+    pub width: usize,
+}
+
+/// The layout of a circuit's evm-encoded input or output: its fields, in order. Fields are read
+/// by name, so a layout change fails loudly instead of shifting positional reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoLayout {
+    pub name: &'static str,
+    pub fields: &'static [IoField],
+}
+
+/// CombinedSkipCircuit input.
+pub const HEADER_RANGE_INPUT_LAYOUT: IoLayout = IoLayout {
+    name: "header range input",
+    fields: &[
+        IoField {
+            name: "trusted_block",
+            width: 8,
+        },
+        IoField {
+            name: "trusted_header_hash",
+            width: 32,
+        },
+        IoField {
+            name: "target_block",
+            width: 8,
+        },
+    ],
+};
+
+/// CombinedSkipCircuit output.
+pub const HEADER_RANGE_OUTPUT_LAYOUT: IoLayout = IoLayout {
+    name: "header range output",
+    fields: &[
+        IoField {
+            name: "target_header_hash",
+            width: 32,
+        },
+        IoField {
+            name: "data_commitment",
+            width: 32,
+        },
+    ],
+};
+
+/// CombinedStepCircuit input.
+pub const NEXT_HEADER_INPUT_LAYOUT: IoLayout = IoLayout {
+    name: "next header input",
+    fields: &[
+        IoField {
+            name: "prev_block",
+            width: 8,
+        },
+        IoField {
+            name: "prev_header_hash",
+            width: 32,
+        },
+    ],
+};
+
+/// CombinedStepCircuit output.
+pub const NEXT_HEADER_OUTPUT_LAYOUT: IoLayout = IoLayout {
+    name: "next header output",
+    fields: &[
+        IoField {
+            name: "next_header_hash",
+            width: 32,
+        },
+        IoField {
+            name: "data_commitment",
+            width: 32,
+        },
+    ],
+};
+
+/// DataCommitmentWithDataHashesCircuit input. Its output has a variable number of leaves, see
+/// DataCommitmentLeaves::from_io.
+pub const DATA_COMMITMENT_INPUT_LAYOUT: IoLayout = IoLayout {
+    name: "data commitment input",
+    fields: &[
+        IoField {
+            name: "start_block",
+            width: 8,
+        },
+        IoField {
+            name: "start_header_hash",
+            width: 32,
+        },
+        IoField {
+            name: "end_block",
+            width: 8,
+        },
+        IoField {
+            name: "end_header_hash",
+            width: 32,
+        },
+    ],
+};
+
+impl IoLayout {
+    pub fn size_bytes(&self) -> usize {
+        self.fields.iter().map(|field| field.width).sum()
+    }
+
+    /// Split bytes into the fields of the layout. Errors if their length doesn't match the layout.
+    pub fn read<'a>(&self, bytes: &'a [u8]) -> Result<IoValues<'a>> {
+        ensure!(
+            bytes.len() == self.size_bytes(),
+            "invalid {} length {}, expected {}",
+            self.name,
+            bytes.len(),
+            self.size_bytes()
+        );
+        let mut values = BTreeMap::new();
+        let mut offset = 0;
+        for field in self.fields {
+            values.insert(field.name, &bytes[offset..offset + field.width]);
+            offset += field.width;
+        }
+        Ok(IoValues {
+            layout: *self,
+            values,
+        })
+    }
+}
+
+/// The fields of an evm-encoded input or output, read by IoLayout::read.
+#[derive(Debug, Clone)]
+pub struct IoValues<'a> {
+    layout: IoLayout,
+    values: BTreeMap<&'static str, &'a [u8]>,
+}
+
+impl IoValues<'_> {
+    fn get(&self, name: &str, width: usize) -> Result<&[u8]> {
+        let value = self
+            .values
+            .get(name)
+            .ok_or_else(|| anyhow!("{} has no field named {}", self.layout.name, name))?;
+        ensure!(
+            value.len() == width,
+            "{} field {} is {} bytes, expected {}",
+            self.layout.name,
+            name,
+            value.len(),
+            width
+        );
+        Ok(value)
+    }
+
+    /// The bytes32 field called name.
+    pub fn bytes32(&self, name: &str) -> Result<[u8; 32]> {
+        Ok(self.get(name, 32)?.try_into()?)
+    }
+
+    /// The big-endian uint64 field called name.
+    pub fn uint64(&self, name: &str) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.get(name, 8)?.try_into()?))
+    }
+}
+
+/// The public values of a header range or next header proof, in a form that doesn't depend on the
+/// proving frontend. Any verifier frontend can consume the same encoding of these values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicValuesV1 {
+    pub trusted_header: [u8; 32],
+    pub trusted_height: u64,
+    pub target_header: [u8; 32],
+    pub target_height: u64,
+    pub data_commitment: [u8; 32],
+}
+
+impl PublicValuesV1 {
+    /// Decode the public values from the evm-encoded input and output of CombinedSkipCircuit.
+    /// Input: trusted_block (uint64) || trusted_header_hash (bytes32) || target_block (uint64).
+    /// Output: target_header_hash (bytes32) || data_commitment (bytes32).
+    pub fn from_header_range_io(input: &[u8], output: &[u8]) -> Result<Self> {
+        let input = HEADER_RANGE_INPUT_LAYOUT.read(input)?;
+        let output = HEADER_RANGE_OUTPUT_LAYOUT.read(output)?;
+
+        Ok(Self {
+            trusted_height: input.uint64("trusted_block")?,
+            trusted_header: input.bytes32("trusted_header_hash")?,
+            target_height: input.uint64("target_block")?,
+            target_header: output.bytes32("target_header_hash")?,
+            data_commitment: output.bytes32("data_commitment")?,
+        })
+    }
+
+    /// Decode the public values from the evm-encoded input and output of CombinedStepCircuit.
+    /// Input: prev_block (uint64) || prev_header_hash (bytes32).
+    /// Output: next_header_hash (bytes32) || data_commitment (bytes32).
+    /// The target height is always prev_block + 1.
+    pub fn from_next_header_io(input: &[u8], output: &[u8]) -> Result<Self> {
+        let input = NEXT_HEADER_INPUT_LAYOUT.read(input)?;
+        let output = NEXT_HEADER_OUTPUT_LAYOUT.read(output)?;
+
+        let trusted_height = input.uint64("prev_block")?;
+        Ok(Self {
+            trusted_height,
+            trusted_header: input.bytes32("prev_header_hash")?,
+            target_height: trusted_height
+                .checked_add(1)
+                .ok_or_else(|| anyhow!("next header trusted height overflows"))?,
+            target_header: output.bytes32("next_header_hash")?,
+            data_commitment: output.bytes32("data_commitment")?,
+        })
+    }
+
+    /// abi.encode(trusted_header, trusted_height, target_header, target_height, data_commitment).
+    /// Each value occupies one 32-byte word, with heights left-padded big-endian integers.
+    pub fn abi_encode(&self) -> Vec<u8> {
+        PublicValuesV1Tuple::abi_encode(&(
+            self.trusted_header,
+            self.trusted_height,
+            self.target_header,
+            self.target_height,
+            self.data_commitment,
+        ))
+    }
+
+    /// Fixed-layout encoding of the public values:
+    ///     trusted_header (32 bytes) || trusted_height (8 bytes, big-endian) ||
+    ///     target_header (32 bytes) || target_height (8 bytes, big-endian) || data_commitment (32 bytes)
+    /// This is abi.encodePacked of the same values.
+    pub fn to_bytes(&self) -> [u8; PUBLIC_VALUES_V1_SIZE_BYTES] {
+        let mut bytes = [0u8; PUBLIC_VALUES_V1_SIZE_BYTES];
+        bytes[0..32].copy_from_slice(&self.trusted_header);
+        bytes[32..40].copy_from_slice(&self.trusted_height.to_be_bytes());
+        bytes[40..72].copy_from_slice(&self.target_header);
+        bytes[72..80].copy_from_slice(&self.target_height.to_be_bytes());
+        bytes[80..112].copy_from_slice(&self.data_commitment);
+        bytes
+    }
+}
+
+/// The public values of a DataCommitmentWithDataHashesCircuit proof: the data commitment of
+/// [start_height, end_height), and the data hash of each block in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataCommitmentLeaves {
+    pub start_header: [u8; 32],
+    pub start_height: u64,
+    pub end_header: [u8; 32],
+    pub end_height: u64,
+    pub data_commitment: [u8; 32],
+    /// The number of leaves data_commitment commits to, end_height - start_height. The circuit's
+    /// padding leaves are not committed, so an attestation key must be below this count.
+    pub committed_leaf_count: u64,
+    /// (height, data_hash) of each block in [start_height, end_height), in increasing height order.
+    pub leaves: Vec<(u64, [u8; 32])>,
+}
+
+impl DataCommitmentLeaves {
+    /// Decode the public values from the evm-encoded input and output of DataCommitmentWithDataHashesCircuit.
+    /// Input: start_block (uint64) || start_header_hash (bytes32) || end_block (uint64) || end_header_hash (bytes32).
+    /// Output: data_commitment (bytes32) || for each of the circuit's MAX_LEAVES leaves: height (uint64) || data_hash (bytes32).
+    /// The leaves at or after end_block are padding, and must have a zero data hash.
+    pub fn from_io(input: &[u8], output: &[u8]) -> Result<Self> {
+        let input = DATA_COMMITMENT_INPUT_LAYOUT.read(input)?;
+        ensure!(
+            output.len() >= 32 && (output.len() - 32) % 40 == 0,
+            "invalid data commitment output length"
+        );
+        let nb_leaves = (output.len() - 32) / 40;
+        ensure!(
+            nb_leaves <= DATA_HASH_OUTPUTS_MAX_LEAVES,
+            "data commitment output has more than {} leaves",
+            DATA_HASH_OUTPUTS_MAX_LEAVES
+        );
+
+        let start_height = input.uint64("start_block")?;
+        let end_height = input.uint64("end_block")?;
+        ensure!(
+            start_height <= end_height && end_height - start_height <= nb_leaves as u64,
+            "invalid data commitment range [{}, {})",
+            start_height,
+            end_height
+        );
+
+        let mut leaves = Vec::new();
+        for (i, leaf) in output[32..].chunks_exact(40).enumerate() {
+            let height = u64::from_be_bytes(leaf[0..8].try_into()?);
+            let data_hash: [u8; 32] = leaf[8..40].try_into()?;
+            ensure!(
+                Some(height) == start_height.checked_add(i as u64),
+                "leaf {} has height {}, expected {} + {}",
+                i,
+                height,
+                start_height,
+                i
+            );
+            if height < end_height {
+                leaves.push((height, data_hash));
+            } else {
+                ensure!(data_hash == [0u8; 32], "padding leaf {} is not zero", i);
+            }
+        }
+
+        Ok(Self {
+            start_header: input.bytes32("start_header_hash")?,
+            start_height,
+            end_header: input.bytes32("end_header_hash")?,
+            end_height,
+            data_commitment: output[0..32].try_into()?,
+            committed_leaf_count: end_height - start_height,
+            leaves,
+        })
+    }
+}
+
+/// The public values of a proof, by the layout of the circuit's evm-encoded input and output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofOutputs {
+    /// A header range (CombinedSkipCircuit) or next header (CombinedStepCircuit) proof.
+    V1(PublicValuesV1),
+    /// A DataCommitmentWithDataHashesCircuit proof, which also outputs the data hash of each block.
+    WithLeaves(DataCommitmentLeaves),
+}
+
+impl ProofOutputs {
+    /// Decode the public values from the evm-encoded input and output of a proof. The circuits' inputs
+    /// have distinct lengths, so the layout is identified by the input length: 40 bytes for
+    /// CombinedStepCircuit, 48 bytes for CombinedSkipCircuit and 80 bytes for
+    /// DataCommitmentWithDataHashesCircuit.
+    pub fn decode(input: &[u8], output: &[u8]) -> Result<Self> {
+        match input.len() {
+            len if len == NEXT_HEADER_INPUT_LAYOUT.size_bytes() => Ok(Self::V1(
+                PublicValuesV1::from_next_header_io(input, output)?,
+            )),
+            len if len == HEADER_RANGE_INPUT_LAYOUT.size_bytes() => Ok(Self::V1(
+                PublicValuesV1::from_header_range_io(input, output)?,
+            )),
+            len if len == DATA_COMMITMENT_INPUT_LAYOUT.size_bytes() => Ok(Self::WithLeaves(
+                DataCommitmentLeaves::from_io(input, output)?,
+            )),
+            len => Err(anyhow!("unknown proof input length {}", len)),
+        }
+    }
+
+    /// Decode the public values once (see decode), and check them against expected if given.
+    /// A mismatch is reported with every differing field.
+    pub fn decode_and_compare(
+        input: &[u8],
+        output: &[u8],
+        expected: Option<&ProofOutputs>,
+    ) -> std::result::Result<Self, OutputsError> {
+        let outputs = Self::decode(input, output).map_err(OutputsError::Decode)?;
+        if let Some(expected) = expected {
+            let diffs = outputs.diff(expected);
+            if !diffs.is_empty() {
+                return Err(OutputsError::Mismatch(diffs));
+            }
+        }
+        Ok(outputs)
+    }
+
+    /// The fields whose values differ from expected, in layout order. Outputs of different
+    /// layouts differ in the single field "layout".
+    pub fn diff(&self, expected: &ProofOutputs) -> Vec<OutputFieldDiff> {
+        let (expected_fields, actual_fields) = match (expected, self) {
+            (Self::V1(_), Self::V1(_)) | (Self::WithLeaves(_), Self::WithLeaves(_)) => {
+                (expected.named_fields(), self.named_fields())
+            }
+            _ => {
+                return vec![OutputFieldDiff {
+                    field: "layout".to_string(),
+                    expected: expected.layout_name().to_string(),
+                    actual: self.layout_name().to_string(),
+                }]
+            }
+        };
+
+        let actual_values = actual_fields.iter().cloned().collect::<BTreeMap<_, _>>();
+        let expected_values = expected_fields.iter().cloned().collect::<BTreeMap<_, _>>();
+        let missing = "none".to_string();
+        let mut diffs = Vec::new();
+        for (field, expected_value) in &expected_fields {
+            let actual_value = actual_values.get(field).unwrap_or(&missing);
+            if actual_value != expected_value {
+                diffs.push(OutputFieldDiff {
+                    field: field.clone(),
+                    expected: expected_value.clone(),
+                    actual: actual_value.clone(),
+                });
+            }
+        }
+        for (field, actual_value) in &actual_fields {
+            if !expected_values.contains_key(field) {
+                diffs.push(OutputFieldDiff {
+                    field: field.clone(),
+                    expected: missing.clone(),
+                    actual: actual_value.clone(),
+                });
+            }
+        }
+        diffs
+    }
+
+    fn layout_name(&self) -> &'static str {
+        match self {
+            Self::V1(_) => "V1",
+            Self::WithLeaves(_) => "WithLeaves",
+        }
+    }
+
+    /// (name, formatted value) of each public value, in layout order.
+    fn named_fields(&self) -> Vec<(String, String)> {
+        let hex = |bytes: &[u8; 32]| alloy_primitives::hex::encode_prefixed(bytes);
+        match self {
+            Self::V1(public_values) => vec![
+                (
+                    "trusted_header".to_string(),
+                    hex(&public_values.trusted_header),
+                ),
+                (
+                    "trusted_height".to_string(),
+                    public_values.trusted_height.to_string(),
+                ),
+                (
+                    "target_header".to_string(),
+                    hex(&public_values.target_header),
+                ),
+                (
+                    "target_height".to_string(),
+                    public_values.target_height.to_string(),
+                ),
+                (
+                    "data_commitment".to_string(),
+                    hex(&public_values.data_commitment),
+                ),
+            ],
+            Self::WithLeaves(leaves) => {
+                let mut fields = vec![
+                    ("start_header".to_string(), hex(&leaves.start_header)),
+                    ("start_height".to_string(), leaves.start_height.to_string()),
+                    ("end_header".to_string(), hex(&leaves.end_header)),
+                    ("end_height".to_string(), leaves.end_height.to_string()),
+                    ("data_commitment".to_string(), hex(&leaves.data_commitment)),
+                    (
+                        "committed_leaf_count".to_string(),
+                        leaves.committed_leaf_count.to_string(),
+                    ),
+                ];
+                fields.extend(
+                    leaves
+                        .leaves
+                        .iter()
+                        .enumerate()
+                        .map(|(i, (height, data_hash))| {
+                            (
+                                format!("leaves[{}]", i),
+                                format!("({}, {})", height, hex(data_hash)),
+                            )
+                        }),
+                );
+                fields
+            }
+        }
+    }
+}
+
+/// A public value that differs from the expected one, with both values formatted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputFieldDiff {
+    pub field: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// Why the public values of a proof could not be decoded, or differ from the expected ones.
+#[derive(Debug)]
+pub enum OutputsError {
+    Decode(anyhow::Error),
+    Mismatch(Vec<OutputFieldDiff>),
+}
+
+impl fmt::Display for OutputsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(err) => write!(f, "failed to decode proof outputs: {:#}", err),
+            Self::Mismatch(diffs) => {
+                write!(f, "proof outputs differ from the expected outputs:")?;
+                for diff in diffs {
+                    write!(
+                        f,
+                        " {} (expected {}, got {})",
+                        diff.field, diff.expected, diff.actual
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for OutputsError {}
+
+/// A small JSON sidecar describing a generated proof, so operators can index and track proofs
+/// without parsing them. Hashes are 0x-prefixed hex strings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofMetadata {
+    pub trusted_height: u64,
+    pub target_height: u64,
+    pub target_header: String,
+    pub data_commitment: String,
+    /// cache::circuit_parameters_hash of the circuit that generated the proof.
+    pub circuit_parameters_hash: String,
+    /// Digest of the circuit's verifier data, which identifies the verifier that accepts the proof.
+    pub circuit_digest: String,
+    /// Unix timestamp (in seconds) at which the proof was generated.
+    pub timestamp: u64,
+}
+
+impl ProofMetadata {
+    pub fn new(
+        public_values: &PublicValuesV1,
+        circuit_parameters_hash: [u8; 32],
+        circuit_digest: &[u8],
+        timestamp: u64,
+    ) -> Self {
+        Self {
+            trusted_height: public_values.trusted_height,
+            target_height: public_values.target_height,
+            target_header: alloy_primitives::hex::encode_prefixed(public_values.target_header),
+            data_commitment: alloy_primitives::hex::encode_prefixed(public_values.data_commitment),
+            circuit_parameters_hash: alloy_primitives::hex::encode_prefixed(
+                circuit_parameters_hash,
+            ),
+            circuit_digest: alloy_primitives::hex::encode_prefixed(circuit_digest),
+            timestamp,
+        }
+    }
+}
+
+/// Two proofs claim different header hashes for the same height. At most one of them can be valid,
+/// so this indicates a soundness bug in the circuits or in how the proofs were verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderHashConflict {
+    pub height: u64,
+    pub first_header: [u8; 32],
+    pub second_header: [u8; 32],
+}
+
+impl fmt::Display for HeaderHashConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "conflicting proven header hashes at height {}: 0x{} and 0x{}",
+            self.height,
+            alloy_primitives::hex::encode(self.first_header),
+            alloy_primitives::hex::encode(self.second_header)
+        )
+    }
+}
+
+impl std::error::Error for HeaderHashConflict {}
+
+/// The header hashes proven by a set of proofs, ordered by height.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProvenHeaderHashes {
+    pub header_hashes: BTreeMap<u64, [u8; 32]>,
+    /// Ranges (from_height, to_height) of the requested range that no proof links, i.e. there is no
+    /// chain of proofs from the header at from_height to the header at to_height.
+    pub gaps: Vec<(u64, u64)>,
+}
+
+impl ProvenHeaderHashes {
+    /// Iterate over the proven (height, header hash) pairs in increasing height order.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, [u8; 32])> + '_ {
+        self.header_hashes
+            .iter()
+            .map(|(height, header)| (*height, *header))
+    }
+}
+
+/// Collect the header hashes proven by the public values of a set of proofs, and the gaps in their
+/// coverage of [start_height, end_height]. Each proof links the header at trusted_height to the
+/// header at target_height. Proofs may overlap, but must agree on the header hash of every height
+/// they share.
+pub fn stitch_header_hashes(
+    proofs: &[PublicValuesV1],
+    start_height: u64,
+    end_height: u64,
+) -> std::result::Result<ProvenHeaderHashes, HeaderHashConflict> {
+    let mut header_hashes = BTreeMap::new();
+    for proof in proofs {
+        for (height, header) in [
+            (proof.trusted_height, proof.trusted_header),
+            (proof.target_height, proof.target_header),
+        ] {
+            if height < start_height || height > end_height {
+                continue;
+            }
+            let first_header = *header_hashes.entry(height).or_insert(header);
+            if first_header != header {
+                return Err(HeaderHashConflict {
+                    height,
+                    first_header,
+                    second_header: header,
+                });
+            }
+        }
+    }
+
+    let mut links = proofs
+        .iter()
+        .map(|proof| (proof.trusted_height, proof.target_height))
+        .collect::<Vec<_>>();
+    links.sort_unstable();
+
+    // Walk the links in order of their trusted height, tracking the heights linked so far. A link
+    // only extends the chain if its trusted header is itself linked: otherwise its trusted header
+    // is just an unproven input. A link whose trusted height is past every linked height starts a
+    // new chain after a gap. As every link's target height is above its trusted height, a link's
+    // trusted height is linked (if at all) by links earlier in the walk.
+    let mut gaps = Vec::new();
+    let mut linked_heights = BTreeSet::from([start_height]);
+    for (trusted_height, target_height) in links {
+        if trusted_height >= end_height {
+            continue;
+        }
+        let linked_height = *linked_heights.last().unwrap();
+        if !linked_heights.contains(&trusted_height) {
+            if trusted_height < linked_height {
+                continue;
+            }
+            gaps.push((linked_height, trusted_height));
+            linked_heights.insert(trusted_height);
+        }
+        linked_heights.insert(target_height);
+    }
+    let linked_height = *linked_heights.last().unwrap();
+    if linked_height < end_height {
+        gaps.push((linked_height, end_height));
+    }
+
+    Ok(ProvenHeaderHashes {
+        header_hashes,
+        gaps,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    fn test_public_values() -> PublicValuesV1 {
+        PublicValuesV1 {
+            trusted_header: [1u8; 32],
+            trusted_height: 0x0102,
+            target_header: [2u8; 32],
+            target_height: 0x0506,
+            data_commitment: [3u8; 32],
+        }
+    }
+
+    #[test]
+    fn test_public_values_to_bytes() {
+        let mut expected_bytes = Vec::new();
+        expected_bytes.extend([1u8; 32]);
+        expected_bytes.extend([0, 0, 0, 0, 0, 0, 1, 2]);
+        expected_bytes.extend([2u8; 32]);
+        expected_bytes.extend([0, 0, 0, 0, 0, 0, 5, 6]);
+        expected_bytes.extend([3u8; 32]);
+
+        assert_eq!(test_public_values().to_bytes().to_vec(), expected_bytes);
+    }
+
+    #[test]
+    fn test_public_values_abi_encode() {
+        let mut expected_bytes = Vec::new();
+        expected_bytes.extend([1u8; 32]);
+        expected_bytes.extend([0u8; 30]);
+        expected_bytes.extend([1, 2]);
+        expected_bytes.extend([2u8; 32]);
+        expected_bytes.extend([0u8; 30]);
+        expected_bytes.extend([5, 6]);
+        expected_bytes.extend([3u8; 32]);
+
+        assert_eq!(test_public_values().abi_encode(), expected_bytes);
+    }
+
+    // Random input and output lengths and contents must be decoded or rejected, never panic.
+    #[test]
+    fn test_fuzz_public_values_from_io() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..10000 {
+            let mut input = vec![0u8; rng.gen_range(0..96)];
+            let mut output = vec![0u8; rng.gen_range(0..96)];
+            rng.fill(&mut input[..]);
+            rng.fill(&mut output[..]);
+
+            let header_range = PublicValuesV1::from_header_range_io(&input, &output);
+            assert_eq!(
+                header_range.is_ok(),
+                input.len() == 48 && output.len() == 64
+            );
+            let _ = PublicValuesV1::from_next_header_io(&input, &output);
+            let _ = ProofOutputs::decode(&input, &output);
+        }
+
+        // The next header's target height can't overflow.
+        let mut input = vec![0xffu8; 8];
+        input.extend([0u8; 32]);
+        assert!(PublicValuesV1::from_next_header_io(&input, &[0u8; 64]).is_err());
+    }
+
+    #[test]
+    fn test_public_values_from_io() {
+        let public_values = test_public_values();
+
+        let mut header_range_input = Vec::new();
+        header_range_input.extend(public_values.trusted_height.to_be_bytes());
+        header_range_input.extend(public_values.trusted_header);
+        header_range_input.extend(public_values.target_height.to_be_bytes());
+
+        let mut output = Vec::new();
+        output.extend(public_values.target_header);
+        output.extend(public_values.data_commitment);
+
+        assert_eq!(
+            PublicValuesV1::from_header_range_io(&header_range_input, &output).unwrap(),
+            public_values
+        );
+        assert!(PublicValuesV1::from_header_range_io(&header_range_input[..40], &output).is_err());
+
+        let next_header_input = header_range_input[..40].to_vec();
+        let next_header_public_values =
+            PublicValuesV1::from_next_header_io(&next_header_input, &output).unwrap();
+        assert_eq!(
+            next_header_public_values.target_height,
+            public_values.trusted_height + 1
+        );
+    }
+
+    /// The header range fixture read by contracts/test/BlobstreamXCommit.t.sol.
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct HeaderRangeEvmFixture {
+        trusted_height: u64,
+        trusted_header: String,
+        target_height: u64,
+        target_header: String,
+        data_commitment: String,
+        header_range_input: String,
+        header_range_output: String,
+    }
+
+    /// Write the evm-encoded header range IO of the golden public values, as decoded by
+    /// from_header_range_io, to the fixture the contract test commits. A change to the encoding
+    /// rewrites the fixture, and the contract test fails until the contract matches it.
+    #[test]
+    fn test_write_header_range_evm_fixture() {
+        let public_values = test_public_values();
+        let mut input = Vec::new();
+        input.extend(public_values.trusted_height.to_be_bytes());
+        input.extend(public_values.trusted_header);
+        input.extend(public_values.target_height.to_be_bytes());
+        let mut output = Vec::new();
+        output.extend(public_values.target_header);
+        output.extend(public_values.data_commitment);
+        assert_eq!(
+            PublicValuesV1::from_header_range_io(&input, &output).unwrap(),
+            public_values
+        );
+
+        let fixture = HeaderRangeEvmFixture {
+            trusted_height: public_values.trusted_height,
+            trusted_header: alloy_primitives::hex::encode_prefixed(public_values.trusted_header),
+            target_height: public_values.target_height,
+            target_header: alloy_primitives::hex::encode_prefixed(public_values.target_header),
+            data_commitment: alloy_primitives::hex::encode_prefixed(public_values.data_commitment),
+            header_range_input: alloy_primitives::hex::encode_prefixed(&input),
+            header_range_output: alloy_primitives::hex::encode_prefixed(&output),
+        };
+        std::fs::write(
+            "contracts/test/fixtures/header_range_v1.json",
+            serde_json::to_string_pretty(&fixture).unwrap() + "\n",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_io_layout_read_by_name() {
+        let mut input = Vec::new();
+        input.extend(10u64.to_be_bytes());
+        input.extend([1u8; 32]);
+        input.extend(20u64.to_be_bytes());
+        assert_eq!(HEADER_RANGE_INPUT_LAYOUT.size_bytes(), 48);
+
+        let values = HEADER_RANGE_INPUT_LAYOUT.read(&input).unwrap();
+        assert_eq!(values.uint64("trusted_block").unwrap(), 10);
+        assert_eq!(values.bytes32("trusted_header_hash").unwrap(), [1u8; 32]);
+        assert_eq!(values.uint64("target_block").unwrap(), 20);
+        assert_eq!(
+            values.bytes32("target_block").unwrap_err().to_string(),
+            "header range input field target_block is 8 bytes, expected 32"
+        );
+        assert_eq!(
+            HEADER_RANGE_INPUT_LAYOUT
+                .read(&input[..40])
+                .unwrap_err()
+                .to_string(),
+            "invalid header range input length 40, expected 48"
+        );
+
+        // Renaming a field in the layout makes lookups of the old name fail loudly.
+        let renamed_layout = IoLayout {
+            name: "header range input",
+            fields: &[
+                IoField {
+                    name: "trusted_height",
+                    width: 8,
+                },
+                IoField {
+                    name: "trusted_header_hash",
+                    width: 32,
+                },
+                IoField {
+                    name: "target_block",
+                    width: 8,
+                },
+            ],
+        };
+        let values = renamed_layout.read(&input).unwrap();
+        assert_eq!(
+            values.uint64("trusted_block").unwrap_err().to_string(),
+            "header range input has no field named trusted_block"
+        );
+    }
+
+    #[test]
+    fn test_proof_outputs_decode() {
+        let public_values = test_public_values();
+        let mut output = Vec::new();
+        output.extend(public_values.target_header);
+        output.extend(public_values.data_commitment);
+
+        let mut header_range_input = Vec::new();
+        header_range_input.extend(public_values.trusted_height.to_be_bytes());
+        header_range_input.extend(public_values.trusted_header);
+        header_range_input.extend(public_values.target_height.to_be_bytes());
+        assert_eq!(
+            ProofOutputs::decode(&header_range_input, &output).unwrap(),
+            ProofOutputs::V1(public_values)
+        );
+
+        // A window of 3 blocks, padded to 4 leaves.
+        let mut input = Vec::new();
+        input.extend(10u64.to_be_bytes());
+        input.extend([1u8; 32]);
+        input.extend(13u64.to_be_bytes());
+        input.extend([2u8; 32]);
+        let mut output = [3u8; 32].to_vec();
+        for i in 0..4u8 {
+            output.extend((10 + i as u64).to_be_bytes());
+            output.extend(if i < 3 { [4 + i; 32] } else { [0u8; 32] });
+        }
+        assert_eq!(
+            ProofOutputs::decode(&input, &output).unwrap(),
+            ProofOutputs::WithLeaves(DataCommitmentLeaves {
+                start_header: [1u8; 32],
+                start_height: 10,
+                end_header: [2u8; 32],
+                end_height: 13,
+                data_commitment: [3u8; 32],
+                committed_leaf_count: 3,
+                leaves: vec![(10, [4u8; 32]), (11, [5u8; 32]), (12, [6u8; 32])],
+            })
+        );
+
+        // Non-zero padding, out of order heights and truncated leaves are rejected.
+        let mut bad_output = output.clone();
+        bad_output[32 + 3 * 40 + 8] = 1;
+        assert!(ProofOutputs::decode(&input, &bad_output).is_err());
+        let mut bad_output = output.clone();
+        bad_output[32 + 7] = 11;
+        assert!(ProofOutputs::decode(&input, &bad_output).is_err());
+        assert!(ProofOutputs::decode(&input, &output[..32 + 2 * 40]).is_err());
+        assert!(ProofOutputs::decode(&input[..72], &output).is_err());
+    }
+
+    #[test]
+    fn test_proof_outputs_decode_and_compare() {
+        let public_values = test_public_values();
+        let mut input = Vec::new();
+        input.extend(public_values.trusted_height.to_be_bytes());
+        input.extend(public_values.trusted_header);
+        input.extend(public_values.target_height.to_be_bytes());
+        let mut output = Vec::new();
+        output.extend(public_values.target_header);
+        output.extend(public_values.data_commitment);
+
+        let expected = ProofOutputs::V1(public_values);
+        assert_eq!(
+            ProofOutputs::decode_and_compare(&input, &output, Some(&expected)).unwrap(),
+            expected
+        );
+        assert_eq!(
+            ProofOutputs::decode_and_compare(&input, &output, None).unwrap(),
+            expected
+        );
+
+        // The mismatch names each differing field.
+        let mut other_output = output.clone();
+        other_output[63] = 0;
+        let err =
+            ProofOutputs::decode_and_compare(&input, &other_output, Some(&expected)).unwrap_err();
+        let OutputsError::Mismatch(diffs) = &err else {
+            panic!("expected a mismatch, got {}", err);
+        };
+        assert_eq!(
+            *diffs,
+            vec![OutputFieldDiff {
+                field: "data_commitment".to_string(),
+                expected: alloy_primitives::hex::encode_prefixed([3u8; 32]),
+                actual: alloy_primitives::hex::encode_prefixed(
+                    [[3u8; 31].as_slice(), &[0u8]].concat()
+                ),
+            }]
+        );
+        assert!(err.to_string().starts_with(
+            "proof outputs differ from the expected outputs: data_commitment (expected 0x0303"
+        ));
+
+        // Outputs of a different layout, and undecodable outputs.
+        let leaves = ProofOutputs::WithLeaves(DataCommitmentLeaves {
+            start_header: [1u8; 32],
+            start_height: 10,
+            end_header: [2u8; 32],
+            end_height: 10,
+            data_commitment: [3u8; 32],
+            committed_leaf_count: 0,
+            leaves: vec![],
+        });
+        let OutputsError::Mismatch(diffs) =
+            ProofOutputs::decode_and_compare(&input, &output, Some(&leaves)).unwrap_err()
+        else {
+            panic!("expected a mismatch");
+        };
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].field, "layout");
+        assert!(matches!(
+            ProofOutputs::decode_and_compare(&input[..47], &output, Some(&expected)),
+            Err(OutputsError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn test_proof_metadata_serialization() {
+        let metadata = ProofMetadata::new(&test_public_values(), [4u8; 32], &[5u8; 32], 1700000000);
+        assert_eq!(metadata.target_height, 0x0506);
+        assert_eq!(metadata.data_commitment, format!("0x{}", "03".repeat(32)));
+
+        let json = serde_json::to_string(&metadata).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ProofMetadata>(&json).unwrap(),
+            metadata
+        );
+    }
+
+    fn link(trusted_height: u64, target_height: u64, target_header: u8) -> PublicValuesV1 {
+        PublicValuesV1 {
+            trusted_header: [trusted_height as u8; 32],
+            trusted_height,
+            target_header: [target_header; 32],
+            target_height,
+            data_commitment: [0u8; 32],
+        }
+    }
+
+    #[test]
+    fn test_stitch_header_hashes() {
+        // [10, 20] and [20, 30] chain, [15, 20] overlaps them, and [40, 50] is disjoint.
+        let proofs = [
+            link(20, 30, 30),
+            link(10, 20, 20),
+            link(15, 20, 20),
+            link(40, 50, 50),
+        ];
+
+        let proven = stitch_header_hashes(&proofs, 10, 60).unwrap();
+        assert_eq!(
+            proven.iter().map(|(height, _)| height).collect::<Vec<_>>(),
+            vec![10, 15, 20, 30, 40, 50]
+        );
+        assert_eq!(proven.header_hashes[&20], [20u8; 32]);
+        assert_eq!(proven.gaps, vec![(30, 40), (50, 60)]);
+
+        let proven = stitch_header_hashes(&proofs, 10, 30).unwrap();
+        assert_eq!(proven.header_hashes.len(), 4);
+        assert!(proven.gaps.is_empty());
+
+        // Header 15 is only [15, 30]'s unproven trusted input, so 30 isn't linked to 10.
+        let proven = stitch_header_hashes(&[link(10, 20, 20), link(15, 30, 30)], 10, 30).unwrap();
+        assert_eq!(proven.gaps, vec![(20, 30)]);
+
+        // Neither is a proof whose trusted height is below start_height.
+        let proven = stitch_header_hashes(&[link(5, 30, 30)], 10, 30).unwrap();
+        assert_eq!(proven.gaps, vec![(10, 30)]);
+    }
+
+    #[test]
+    fn test_stitch_header_hashes_conflict() {
+        // Both proofs claim a header hash at height 20, and they differ.
+        let proofs = [link(10, 20, 20), link(15, 20, 21)];
+
+        let conflict = stitch_header_hashes(&proofs, 10, 20).unwrap_err();
+        assert_eq!(
+            conflict,
+            HeaderHashConflict {
+                height: 20,
+                first_header: [20u8; 32],
+                second_header: [21u8; 32],
+            }
+        );
+    }
+}
@@ -0,0 +1,322 @@
+use anyhow::{anyhow, Result};
+use ethers::types::H256;
+use plonky2x::prelude::*;
+use sha2::{Digest, Sha256};
+use subtle_encoding::{base64, hex};
+
+use crate::consts::*;
+
+/// Compute the Tendermint address of a validator, which is the first 20 bytes of sha256(pubkey).
+/// Matches `PubKey.Address()` in celestia-core's crypto/ed25519 package.
+pub fn validator_address(pubkey: &[u8; 32]) -> [u8; VALIDATOR_ADDRESS_SIZE_BYTES] {
+    Sha256::digest(pubkey)[..VALIDATOR_ADDRESS_SIZE_BYTES]
+        .try_into()
+        .unwrap()
+}
+
+/// Reverse the byte order of a 32-byte value. This is the off-circuit equivalent of
+/// UtilsBuilder::reverse_bytes32.
+pub fn reverse_bytes32(bytes: &[u8; 32]) -> [u8; 32] {
+    let mut reversed = *bytes;
+    reversed.reverse();
+    reversed
+}
+
+/// A 32-byte hash (e.g. a header hash, data hash or data commitment), in the byte order
+/// celestia-core reports it in. Hashes enter the crate from RPC responses and native headers, and
+/// leave it as witness values, only through this type, which never reorders their bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Hash32(pub [u8; 32]);
+
+impl Hash32 {
+    /// Decode a hash from a celestia-core RPC response. JSON-RPC responses encode hashes as hex (64
+    /// characters, in either case), while protobuf JSON encodes them as base64 (44 characters).
+    pub fn from_rpc_hex(encoded: &str) -> Result<Self> {
+        let decoded = match encoded.len() {
+            64 => hex::decode_upper(encoded.to_uppercase())
+                .map_err(|e| anyhow!("invalid hex hash {}: {}", encoded, e))?,
+            44 => base64::decode(encoded)
+                .map_err(|e| anyhow!("invalid base64 hash {}: {}", encoded, e))?,
+            _ => {
+                return Err(anyhow!(
+                    "invalid hash length {}: {}",
+                    encoded.len(),
+                    encoded
+                ))
+            }
+        };
+        let bytes: [u8; 32] = decoded
+            .try_into()
+            .map_err(|_| anyhow!("hash {} does not decode to 32 bytes", encoded))?;
+        Ok(Self(bytes))
+    }
+
+    /// Encode the hash as celestia-core's JSON-RPC does: upper case hex, without a 0x prefix.
+    pub fn to_rpc_hex(&self) -> String {
+        String::from_utf8(hex::encode_upper(self.0)).unwrap()
+    }
+}
+
+impl From<[u8; 32]> for Hash32 {
+    fn from(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl TryFrom<tendermint::Hash> for Hash32 {
+    type Error = anyhow::Error;
+
+    fn try_from(hash: tendermint::Hash) -> Result<Self> {
+        let bytes: [u8; 32] = hash
+            .as_bytes()
+            .try_into()
+            .map_err(|_| anyhow!("expected a sha256 hash, got {:?}", hash))?;
+        Ok(Self(bytes))
+    }
+}
+
+impl From<Hash32> for H256 {
+    fn from(hash: Hash32) -> Self {
+        H256(hash.0)
+    }
+}
+
+/// Gas per zero and non-zero byte of calldata (EIP-2028).
+const CALLDATA_ZERO_BYTE_GAS: u64 = 4;
+const CALLDATA_NON_ZERO_BYTE_GAS: u64 = 16;
+
+/// Estimate the calldata gas of submitting a proof and its public inputs on Ethereum, using the
+/// EIP-2028 gas schedule. This excludes the base transaction cost and execution gas.
+pub fn estimate_calldata_gas(proof: &[u8], public_inputs: &[u8]) -> u64 {
+    proof
+        .iter()
+        .chain(public_inputs.iter())
+        .map(|byte| {
+            if *byte == 0 {
+                CALLDATA_ZERO_BYTE_GAS
+            } else {
+                CALLDATA_NON_ZERO_BYTE_GAS
+            }
+        })
+        .sum()
+}
+
+pub trait UtilsBuilder<L: PlonkParameters<D>, const D: usize> {
+    /// Reverse the byte order of a Bytes32Variable. This only rewires the bytes, and adds no
+    /// constraints.
+    /// Note: No circuit in this crate converts byte orientation, since Tendermint and the EVM use the
+    /// same byte order for hashes (see test_hash_byte_order_round_trip).
+    fn reverse_bytes32(&mut self, bytes: Bytes32Variable) -> Bytes32Variable;
+
+    /// Assert a - b == diff. Fails if a < b, rather than comparing against a wrapped difference.
+    fn assert_difference_eq(&mut self, a: U64Variable, b: U64Variable, diff: u64);
+
+    /// Assert value equals at least one of the candidates, and return the index of the first
+    /// matching candidate.
+    fn assert_is_one_of(
+        &mut self,
+        value: Bytes32Variable,
+        candidates: &[Bytes32Variable],
+    ) -> U64Variable;
+
+    /// Returns true if all bytes of value are zero.
+    fn is_zero_bytes32(&mut self, value: Bytes32Variable) -> BoolVariable;
+}
+
+impl<L: PlonkParameters<D>, const D: usize> UtilsBuilder<L, D> for CircuitBuilder<L, D> {
+    fn reverse_bytes32(&mut self, bytes: Bytes32Variable) -> Bytes32Variable {
+        let mut reversed = bytes.as_bytes();
+        reversed.reverse();
+        Bytes32Variable::from(&reversed[..])
+    }
+
+    fn assert_difference_eq(&mut self, a: U64Variable, b: U64Variable, diff: u64) {
+        let true_var = self._true();
+        let diff_var = self.constant::<U64Variable>(diff);
+
+        // Check a >= b before subtracting, as the subtraction wraps on underflow.
+        let no_underflow = self.lte(b, a);
+        self.assert_is_equal(no_underflow, true_var);
+
+        let computed_diff = self.sub(a, b);
+        self.assert_is_equal(computed_diff, diff_var);
+    }
+
+    fn assert_is_one_of(
+        &mut self,
+        value: Bytes32Variable,
+        candidates: &[Bytes32Variable],
+    ) -> U64Variable {
+        assert!(!candidates.is_empty(), "candidates must not be empty");
+
+        let true_var = self._true();
+        let mut is_match = self._false();
+        let mut matched_idx = self.constant::<U64Variable>(0);
+
+        // Iterate in reverse, so the first matching candidate's index is selected last.
+        for (i, candidate) in candidates.iter().enumerate().rev() {
+            let is_curr_match = self.is_equal(value, *candidate);
+            let curr_idx = self.constant::<U64Variable>(i as u64);
+            matched_idx = self.select(is_curr_match, curr_idx, matched_idx);
+            is_match = self.or(is_match, is_curr_match);
+        }
+
+        // Assert value matched at least one of the candidates.
+        self.assert_is_equal(is_match, true_var);
+
+        matched_idx
+    }
+
+    fn is_zero_bytes32(&mut self, value: Bytes32Variable) -> BoolVariable {
+        let zero = self.constant::<Bytes32Variable>(H256::zero());
+        self.is_equal(value, zero)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type L = DefaultParameters;
+    const D: usize = 2;
+
+    #[test]
+    fn test_reverse_bytes32() {
+        env_logger::try_init().unwrap_or_default();
+
+        let bytes: [u8; 32] = core::array::from_fn(|i| i as u8);
+        let expected_reversed: [u8; 32] = core::array::from_fn(|i| 31 - i as u8);
+        assert_eq!(reverse_bytes32(&bytes), expected_reversed);
+        assert_eq!(reverse_bytes32(&reverse_bytes32(&bytes)), bytes);
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        let bytes_var = builder.read::<Bytes32Variable>();
+        let reversed = builder.reverse_bytes32(bytes_var);
+        let round_trip = builder.reverse_bytes32(reversed);
+        builder.assert_is_equal(round_trip, bytes_var);
+        builder.write(reversed);
+
+        let circuit = builder.build();
+
+        let mut input = circuit.input();
+        input.write::<Bytes32Variable>(H256(bytes));
+        let (proof, mut output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+
+        let reversed_value = output.read::<Bytes32Variable>();
+        assert_eq!(reversed_value, H256(expected_reversed));
+    }
+
+    #[test]
+    fn test_hash32_rpc_hex() {
+        // Header hash of block 10000, in both the JSON-RPC (hex) and protobuf JSON (base64) forms.
+        let hex_hash = "A0123D5E4B8B8888A61F931EE2252D83568B97C223E0ECA9795B29B8BD8CBA2D";
+        let base64_hash = "oBI9XkuLiIimH5Me4iUtg1aLl8Ij4OypeVspuL2Mui0=";
+
+        let expected = Hash32::from_rpc_hex(hex_hash).unwrap();
+        assert_eq!(expected.0[0], 0xa0);
+        assert_eq!(expected.0[31], 0x2d);
+        assert_eq!(expected.to_rpc_hex(), hex_hash);
+        assert_eq!(
+            Hash32::from_rpc_hex(&hex_hash.to_lowercase()).unwrap(),
+            expected
+        );
+        assert_eq!(Hash32::from_rpc_hex(base64_hash).unwrap(), expected);
+        assert_eq!(H256::from(expected).as_bytes(), expected.0);
+
+        // Malformed encodings and hashes that aren't 32 bytes are rejected.
+        assert!(Hash32::from_rpc_hex(&hex_hash.replace('A', "Z")).is_err());
+        assert!(Hash32::from_rpc_hex(&base64_hash.replace('o', "!")).is_err());
+        assert!(Hash32::from_rpc_hex(&hex_hash[..62]).is_err());
+        assert!(Hash32::from_rpc_hex("oBI9XkuLiIimH5Me4iUtg1aLl8Ij4OypeVspuL2Mu===").is_err());
+
+        assert_eq!(
+            Hash32::try_from(tendermint::Hash::Sha256(expected.0)).unwrap(),
+            expected
+        );
+        assert!(Hash32::try_from(tendermint::Hash::None).is_err());
+    }
+
+    #[test]
+    fn test_estimate_calldata_gas() {
+        // 3 zero bytes and 4 non-zero bytes: 3 * 4 + 4 * 16 = 76.
+        let proof = [0x00, 0x01, 0xff];
+        let public_inputs = [0x00, 0x00, 0x10, 0x20];
+        assert_eq!(estimate_calldata_gas(&proof, &public_inputs), 76);
+        assert_eq!(estimate_calldata_gas(&[], &[]), 0);
+    }
+
+    fn test_assert_difference_eq_template(a: u64, b: u64, diff: u64) {
+        env_logger::try_init().unwrap_or_default();
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        let a_var = builder.read::<U64Variable>();
+        let b_var = builder.read::<U64Variable>();
+        builder.assert_difference_eq(a_var, b_var, diff);
+
+        let circuit = builder.build();
+
+        let mut input = circuit.input();
+        input.write::<U64Variable>(a);
+        input.write::<U64Variable>(b);
+        let (proof, output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+    }
+
+    #[test]
+    fn test_assert_difference_eq() {
+        test_assert_difference_eq_template(10004, 10000, 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "was set twice with different values")]
+    fn test_assert_difference_eq_wrong_difference() {
+        test_assert_difference_eq_template(10005, 10000, 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "was set twice with different values")]
+    fn test_assert_difference_eq_underflow() {
+        // 0 - 1 wraps to u64::MAX, which must not pass as a difference of u64::MAX.
+        test_assert_difference_eq_template(0, 1, u64::MAX);
+    }
+
+    fn test_assert_is_one_of_template(value: H256) -> u64 {
+        env_logger::try_init().unwrap_or_default();
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        let value_var = builder.read::<Bytes32Variable>();
+        let candidates = (0..3)
+            .map(|_| builder.read::<Bytes32Variable>())
+            .collect::<Vec<_>>();
+        let matched_idx = builder.assert_is_one_of(value_var, &candidates);
+        builder.write(matched_idx);
+
+        let circuit = builder.build();
+
+        let mut input = circuit.input();
+        input.write::<Bytes32Variable>(value);
+        input.write::<Bytes32Variable>(H256::repeat_byte(1));
+        input.write::<Bytes32Variable>(H256::repeat_byte(2));
+        input.write::<Bytes32Variable>(H256::repeat_byte(3));
+        let (proof, mut output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+
+        output.read::<U64Variable>()
+    }
+
+    #[test]
+    fn test_assert_is_one_of() {
+        assert_eq!(test_assert_is_one_of_template(H256::repeat_byte(1)), 0);
+        assert_eq!(test_assert_is_one_of_template(H256::repeat_byte(3)), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "was set twice with different values")]
+    fn test_assert_is_one_of_no_match() {
+        test_assert_is_one_of_template(H256::repeat_byte(4));
+    }
+}
@@ -0,0 +1,113 @@
+use plonky2x::prelude::*;
+
+use crate::consts::*;
+
+pub trait TendermintValidatorBuilder<L: PlonkParameters<D>, const D: usize> {
+    /// Compute the Tendermint address of a validator, which is the first 20 bytes of sha256(pubkey).
+    /// Commit signatures reference validators by address, so this maps a signature to its entry
+    /// in the validator set.
+    fn validator_address(
+        &mut self,
+        pubkey: &Bytes32Variable,
+    ) -> BytesVariable<VALIDATOR_ADDRESS_SIZE_BYTES>;
+
+    /// Whether two validator addresses are equal.
+    fn is_equal_address(
+        &mut self,
+        a: &BytesVariable<VALIDATOR_ADDRESS_SIZE_BYTES>,
+        b: &BytesVariable<VALIDATOR_ADDRESS_SIZE_BYTES>,
+    ) -> BoolVariable;
+}
+
+impl<L: PlonkParameters<D>, const D: usize> TendermintValidatorBuilder<L, D>
+    for CircuitBuilder<L, D>
+{
+    fn validator_address(
+        &mut self,
+        pubkey: &Bytes32Variable,
+    ) -> BytesVariable<VALIDATOR_ADDRESS_SIZE_BYTES> {
+        let pubkey_hash = self.sha256(&pubkey.as_bytes());
+        BytesVariable::<VALIDATOR_ADDRESS_SIZE_BYTES>(
+            pubkey_hash.as_bytes()[..VALIDATOR_ADDRESS_SIZE_BYTES]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    fn is_equal_address(
+        &mut self,
+        a: &BytesVariable<VALIDATOR_ADDRESS_SIZE_BYTES>,
+        b: &BytesVariable<VALIDATOR_ADDRESS_SIZE_BYTES>,
+    ) -> BoolVariable {
+        self.is_equal(*a, *b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::H256;
+    use tendermintx::input::InputDataFetcher;
+    use tokio::runtime::Runtime;
+
+    use super::*;
+    use crate::utils::validator_address;
+
+    type L = DefaultParameters;
+    const D: usize = 2;
+
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    fn test_validator_address() {
+        env_logger::try_init().unwrap_or_default();
+        dotenv::dotenv().ok();
+
+        const NUM_VALIDATORS: usize = 5;
+        const BLOCK: u64 = 10000;
+
+        let input_data_fetcher = InputDataFetcher::default();
+        let rt = Runtime::new().expect("failed to create tokio runtime");
+        let validators = rt.block_on(async {
+            input_data_fetcher
+                .get_validator_set_from_number(BLOCK)
+                .await
+        });
+        assert!(validators.len() >= NUM_VALIDATORS);
+
+        let mut pubkeys = Vec::new();
+        let mut expected_addresses = Vec::new();
+        for validator in validators.iter().take(NUM_VALIDATORS) {
+            let pubkey: [u8; 32] = validator.pub_key.to_bytes().try_into().unwrap();
+            let expected_address: [u8; VALIDATOR_ADDRESS_SIZE_BYTES] =
+                validator.address.as_bytes().try_into().unwrap();
+
+            // The native address should match the address reported by the RPC.
+            assert_eq!(validator_address(&pubkey), expected_address);
+
+            pubkeys.push(H256(pubkey));
+            expected_addresses.push(expected_address);
+        }
+
+        let mut builder = CircuitBuilder::<L, D>::new();
+
+        let pubkeys_var = builder.read::<ArrayVariable<Bytes32Variable, NUM_VALIDATORS>>();
+        let expected_addresses_var = builder
+            .read::<ArrayVariable<BytesVariable<VALIDATOR_ADDRESS_SIZE_BYTES>, NUM_VALIDATORS>>();
+
+        let true_var = builder._true();
+        for i in 0..NUM_VALIDATORS {
+            let address = builder.validator_address(&pubkeys_var[i]);
+            let is_valid_address = builder.is_equal_address(&address, &expected_addresses_var[i]);
+            builder.assert_is_equal(is_valid_address, true_var);
+        }
+
+        let circuit = builder.build();
+
+        let mut input = circuit.input();
+        input.write::<ArrayVariable<Bytes32Variable, NUM_VALIDATORS>>(pubkeys);
+        input.write::<ArrayVariable<BytesVariable<VALIDATOR_ADDRESS_SIZE_BYTES>, NUM_VALIDATORS>>(
+            expected_addresses,
+        );
+        let (proof, output) = circuit.prove(&input);
+        circuit.verify(&proof, &input, &output);
+    }
+}
@@ -1,4 +1,6 @@
-use plonky2x::frontend::merkle::tree::MerkleInclusionProofVariable;
+use std::fmt;
+
+use plonky2x::frontend::merkle::tree::{InclusionProof, MerkleInclusionProofVariable};
 use plonky2x::frontend::uint::uint64::U64Variable;
 use plonky2x::prelude::{
     ArrayVariable, BoolVariable, Bytes32Variable, CircuitBuilder, CircuitVariable, PlonkParameters,
@@ -25,6 +27,82 @@ pub struct DataCommitmentProofVariable<const MAX_LEAVES: usize> {
     >,
 }
 
+/// A native circuit input whose shape doesn't match the circuit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputError {
+    /// An array-typed field has the wrong length. Fields of array elements are named like
+    /// data_hash_proofs[2].proof.
+    LengthMismatch {
+        field: String,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LengthMismatch {
+                field,
+                expected,
+                actual,
+            } => write!(f, "{} has length {}, expected {}", field, actual, expected),
+        }
+    }
+}
+
+impl std::error::Error for InputError {}
+
+impl<const MAX_LEAVES: usize, F: RichField> DataCommitmentProofValueType<MAX_LEAVES, F> {
+    /// Check the length of each array-typed field against MAX_LEAVES and HEADER_PROOF_DEPTH. Writing a value with a wrong
+    /// length into a DataCommitmentProofVariable panics inside the witness writer, so inputs should be validated first.
+    pub fn validate(&self) -> Result<(), InputError> {
+        validate_data_commitment_proofs::<MAX_LEAVES, F>(
+            &self.data_hash_proofs,
+            &self.last_block_id_proofs,
+        )
+    }
+}
+
+/// Check the lengths of the proofs of a DataCommitmentProofValueType before it is built (see
+/// DataCommitmentProofValueType::validate).
+pub fn validate_data_commitment_proofs<const MAX_LEAVES: usize, F: RichField>(
+    data_hash_proofs: &[InclusionProof<HEADER_PROOF_DEPTH, PROTOBUF_HASH_SIZE_BYTES, F>],
+    last_block_id_proofs: &[InclusionProof<HEADER_PROOF_DEPTH, PROTOBUF_BLOCK_ID_SIZE_BYTES, F>],
+) -> Result<(), InputError> {
+    validate_proofs::<MAX_LEAVES, PROTOBUF_HASH_SIZE_BYTES, F>(
+        "data_hash_proofs",
+        data_hash_proofs,
+    )?;
+    validate_proofs::<MAX_LEAVES, PROTOBUF_BLOCK_ID_SIZE_BYTES, F>(
+        "last_block_id_proofs",
+        last_block_id_proofs,
+    )
+}
+
+fn validate_proofs<const MAX_LEAVES: usize, const LEAF_SIZE_BYTES: usize, F: RichField>(
+    field: &str,
+    proofs: &[InclusionProof<HEADER_PROOF_DEPTH, LEAF_SIZE_BYTES, F>],
+) -> Result<(), InputError> {
+    if proofs.len() != MAX_LEAVES {
+        return Err(InputError::LengthMismatch {
+            field: field.to_string(),
+            expected: MAX_LEAVES,
+            actual: proofs.len(),
+        });
+    }
+    for (i, proof) in proofs.iter().enumerate() {
+        if proof.proof.len() != HEADER_PROOF_DEPTH {
+            return Err(InputError::LengthMismatch {
+                field: format!("{}[{}].proof", field, i),
+                expected: HEADER_PROOF_DEPTH,
+                actual: proof.proof.len(),
+            });
+        }
+    }
+    Ok(())
+}
+
 #[derive(Clone, Debug, CircuitVariable)]
 pub struct MapReduceSubchainVariable {
     pub is_enabled: BoolVariable,
@@ -34,3 +112,83 @@ pub struct MapReduceSubchainVariable {
     pub end_header: Bytes32Variable,
     pub data_merkle_root: Bytes32Variable,
 }
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::H256;
+    use plonky2x::prelude::{DefaultParameters, PlonkParameters};
+
+    use super::*;
+
+    type F = <DefaultParameters as PlonkParameters<2>>::Field;
+
+    const MAX_LEAVES: usize = 4;
+
+    fn proof_value(
+        nb_data_hash_proofs: usize,
+        nb_last_block_id_proofs: usize,
+    ) -> DataCommitmentProofValueType<MAX_LEAVES, F> {
+        DataCommitmentProofValueType {
+            start_header: H256::zero(),
+            end_header: H256::zero(),
+            data_hash_proofs: vec![
+                InclusionProof {
+                    proof: vec![H256::zero(); HEADER_PROOF_DEPTH],
+                    leaf: [0u8; PROTOBUF_HASH_SIZE_BYTES],
+                };
+                nb_data_hash_proofs
+            ],
+            last_block_id_proofs: vec![
+                InclusionProof {
+                    proof: vec![H256::zero(); HEADER_PROOF_DEPTH],
+                    leaf: [0u8; PROTOBUF_BLOCK_ID_SIZE_BYTES],
+                };
+                nb_last_block_id_proofs
+            ],
+        }
+    }
+
+    #[test]
+    fn test_validate_data_commitment_proof() {
+        proof_value(MAX_LEAVES, MAX_LEAVES).validate().unwrap();
+
+        for nb_proofs in [MAX_LEAVES - 1, MAX_LEAVES + 1] {
+            assert_eq!(
+                proof_value(nb_proofs, MAX_LEAVES).validate().unwrap_err(),
+                InputError::LengthMismatch {
+                    field: "data_hash_proofs".to_string(),
+                    expected: MAX_LEAVES,
+                    actual: nb_proofs,
+                }
+            );
+            assert_eq!(
+                proof_value(MAX_LEAVES, nb_proofs).validate().unwrap_err(),
+                InputError::LengthMismatch {
+                    field: "last_block_id_proofs".to_string(),
+                    expected: MAX_LEAVES,
+                    actual: nb_proofs,
+                }
+            );
+        }
+
+        let mut value = proof_value(MAX_LEAVES, MAX_LEAVES);
+        value.last_block_id_proofs[2].proof.pop();
+        let err = value.validate().unwrap_err();
+        assert_eq!(
+            err,
+            InputError::LengthMismatch {
+                field: "last_block_id_proofs[2].proof".to_string(),
+                expected: HEADER_PROOF_DEPTH,
+                actual: HEADER_PROOF_DEPTH - 1,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "last_block_id_proofs[2].proof has length {}, expected {}",
+                HEADER_PROOF_DEPTH - 1,
+                HEADER_PROOF_DEPTH
+            )
+        );
+    }
+}
@@ -0,0 +1,82 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.22;
+
+import "forge-std/Test.sol";
+import "../src/BlobstreamX.sol";
+import {ERC1967Proxy} from "@openzeppelin/proxy/ERC1967/ERC1967Proxy.sol";
+import {ISuccinctGateway} from "@succinctx/interfaces/ISuccinctGateway.sol";
+
+/// @notice Commits a header range through a mocked gateway, using the header range IO in
+/// test/fixtures/header_range_v1.json. That fixture is written by test_write_header_range_evm_fixture
+/// in circuits/public_values.rs from the bytes PublicValuesV1::from_header_range_io decodes, so a
+/// change to the Rust encoding changes the fixture and fails this test until the contract matches.
+/// The gateway is mocked with vm.mockCall. Verifying a real wrapped proof against a deployed
+/// verifier (an evm_test_utils or Anvil harness) is not covered here.
+contract BlobstreamXCommitTest is Test {
+    address constant GATEWAY = address(0x5eca);
+    bytes32 constant HEADER_RANGE_FUNCTION_ID = bytes32(uint256(1));
+    bytes32 constant NEXT_HEADER_FUNCTION_ID = bytes32(uint256(2));
+
+    uint64 trustedHeight;
+    bytes32 trustedHeader;
+    uint64 targetHeight;
+    bytes32 targetHeader;
+    bytes32 dataCommitment;
+    bytes headerRangeInput;
+    bytes headerRangeOutput;
+
+    BlobstreamX public blobstream;
+
+    function setUp() public {
+        string memory fixture = vm.readFile(
+            string.concat(vm.projectRoot(), "/test/fixtures/header_range_v1.json")
+        );
+        trustedHeight = uint64(vm.parseJsonUint(fixture, ".trustedHeight"));
+        trustedHeader = vm.parseJsonBytes32(fixture, ".trustedHeader");
+        targetHeight = uint64(vm.parseJsonUint(fixture, ".targetHeight"));
+        targetHeader = vm.parseJsonBytes32(fixture, ".targetHeader");
+        dataCommitment = vm.parseJsonBytes32(fixture, ".dataCommitment");
+        headerRangeInput = vm.parseJsonBytes(fixture, ".headerRangeInput");
+        headerRangeOutput = vm.parseJsonBytes(fixture, ".headerRangeOutput");
+
+        // The gateway only needs code so the contract's calls to it are not rejected.
+        vm.etch(GATEWAY, hex"00");
+
+        BlobstreamX blobstreamImpl = new BlobstreamX();
+        blobstream = BlobstreamX(address(new ERC1967Proxy(address(blobstreamImpl), "")));
+        blobstream.initialize(
+            BlobstreamX.InitParameters({
+                guardian: address(this),
+                gateway: GATEWAY,
+                height: trustedHeight,
+                header: trustedHeader,
+                headerRangeFunctionId: HEADER_RANGE_FUNCTION_ID,
+                nextHeaderFunctionId: NEXT_HEADER_FUNCTION_ID
+            })
+        );
+    }
+
+    function testCommitHeaderRange() public {
+        // The contract builds the circuit input from its own state, and it must be the input the
+        // Rust decoder reads.
+        assertEq(abi.encodePacked(trustedHeight, trustedHeader, targetHeight), headerRangeInput);
+
+        // The circuit's output is target_header || data_commitment.
+        vm.mockCall(
+            GATEWAY,
+            abi.encodeWithSelector(
+                ISuccinctGateway.verifiedCall.selector,
+                HEADER_RANGE_FUNCTION_ID,
+                headerRangeInput
+            ),
+            abi.encode(headerRangeOutput)
+        );
+
+        blobstream.commitHeaderRange(targetHeight);
+
+        assertEq(blobstream.latestBlock(), targetHeight);
+        assertEq(blobstream.blockHeightToHeaderHash(targetHeight), targetHeader);
+        assertEq(blobstream.state_dataCommitments(1), dataCommitment);
+        assertEq(blobstream.state_proofNonce(), 2);
+    }
+}